@@ -1,13 +1,34 @@
-use serde::Serialize;
+use std::collections::HashMap;
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize, Serializer};
 use thiserror::Error;
 
 use crate::{
-    location::Location,
-    ship::{AddShipError, Grid, Ship, ShipDir},
+    hex::{HexCoord, HexDir},
+    location::{Dir, Location, Topology},
+    ship::{AddShipError, Grid, PlaceFleetError, Ship, ShipClass},
+    view::PlayerView,
 };
-const SHIPS_ORDER: [i32; 5] = [2, 3, 3, 4, 5];
+/// The biggest board there can be, so that every row has a letter.
+pub const MAX_SIZE: i32 = 26;
+pub const MAX_DEPTHS: i32 = 3;
+pub const SHIPS_ORDER: [ShipClass; 5] = [
+    ShipClass::Destroyer,
+    ShipClass::Submarine,
+    ShipClass::Cruiser,
+    ShipClass::Battleship,
+    ShipClass::Carrier,
+];
+
+/// Seeds are sent as strings, since JavaScript numbers can't hold every `u64` exactly. Use with
+/// `#[serde(serialize_with = "...")]`.
+pub fn serialize_seed<S: Serializer>(seed: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(seed)
+}
 
-#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Player {
     Player1 = 0,
     Player2 = 1,
@@ -29,20 +50,264 @@ impl Player {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShotOutcome {
+    Miss,
+    /// The shot hit an armoured cell, which can still take more hits.
+    Armoured,
+    /// The shot destroyed a cell of a ship that is still afloat.
+    Hit,
+    Sunk(ShipClass),
+    /// The shot sank a ship, but the rules don't say which one.
+    SunkUnnamed,
+    /// The shot sank a decoy, and the rules say to own up to it.
+    Decoy,
+}
+
+/// How much the shooter is told about what their shots did. Armour is its own rule, so
+/// `Armoured` outcomes are always passed on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Disclosure {
+    /// Only whether each shot hit or missed, so sinking a ship goes unannounced.
+    HitOrMiss,
+    /// Hits, and when a ship has been sunk, but not what it was.
+    Sunk,
+    /// Hits, and the class of every ship that is sunk.
+    #[default]
+    Class,
+}
+
+impl Disclosure {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Disclosure::HitOrMiss => "hits",
+            Disclosure::Sunk => "sinks",
+            Disclosure::Class => "classes",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Disclosure> {
+        [Disclosure::HitOrMiss, Disclosure::Sunk, Disclosure::Class]
+            .into_iter()
+            .find(|d| d.name().eq_ignore_ascii_case(name))
+    }
+
+    /// What the shooter gets told about `outcome`.
+    pub fn disclose(&self, outcome: ShotOutcome) -> ShotOutcome {
+        match (self, outcome) {
+            (Disclosure::HitOrMiss, ShotOutcome::Sunk(_) | ShotOutcome::Decoy) => ShotOutcome::Hit,
+            (Disclosure::Sunk, ShotOutcome::Sunk(_)) => ShotOutcome::SunkUnnamed,
+            _ => outcome,
+        }
+    }
+}
+
+impl ShotOutcome {
+    /// Shoots at `grid`, whose ships were placed in the order given by `fleet`. Sunk decoys are
+    /// passed off as the class they mimic unless `reveal_decoys` is set.
+    pub fn fire(
+        grid: &mut Grid,
+        coords: Location,
+        fleet: &[ShipClass],
+        reveal_decoys: bool,
+    ) -> Self {
+        match grid.guess_grid(coords) {
+            None => ShotOutcome::Miss,
+            Some(i) if !grid.ships[i].is_found(coords) => ShotOutcome::Armoured,
+            Some(i) if grid.ships[i].is_sunk() && grid.ships[i].is_decoy() && reveal_decoys => {
+                ShotOutcome::Decoy
+            }
+            Some(i) if grid.ships[i].is_sunk() => ShotOutcome::Sunk(fleet[i]),
+            Some(_) => ShotOutcome::Hit,
+        }
+    }
+}
+
+/// The rules that can be set separately for each player, so that a stronger player can be given
+/// a handicap.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerRules {
+    /// The size of the player's own grid.
+    pub size: i32,
+    /// The ships the player has to place, in the order they are placed.
+    pub fleet: Vec<ShipClass>,
+    /// How many shots the player fires each turn. Rules where this is zero fail `Rules::check`.
+    pub shots_per_turn: u32,
+    /// Decoys the player places after their fleet, each looking like a ship of the given class.
+    /// They don't have to be sunk to win.
+    #[serde(default)]
+    pub decoys: Vec<ShipClass>,
+}
+
+impl PlayerRules {
+    pub fn standard(size: i32) -> Self {
+        Self {
+            size,
+            fleet: SHIPS_ORDER.to_vec(),
+            shots_per_turn: 1,
+            decoys: vec![],
+        }
+    }
+
+    /// Everything the player places: the fleet, then the decoys.
+    pub fn pieces(&self) -> Vec<ShipClass> {
+        self.fleet.iter().chain(&self.decoys).copied().collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rules {
+    pub players: [PlayerRules; 2],
+    /// The shape of both boards.
+    #[serde(default)]
+    pub topology: Topology,
+    /// How many layers both boards have below the surface. Submarines go in these layers and
+    /// can only be hit by depth charges, which are shots aimed below the surface.
+    #[serde(default)]
+    pub depths: i32,
+    /// How many hits it takes to destroy each cell of a ship, for the classes that need more
+    /// than one.
+    #[serde(default)]
+    pub armour: HashMap<ShipClass, u32>,
+    /// How many times each player can spend a turn repairing a damaged cell instead of firing.
+    #[serde(default)]
+    pub repairs: u32,
+    /// Whether repairs are kept secret from the opponent, who then still sees the cell as hit.
+    #[serde(default)]
+    pub hide_repairs: bool,
+    /// Whether a sunk decoy is announced as a decoy, rather than as the ship it mimics.
+    #[serde(default)]
+    pub reveal_decoys: bool,
+    /// What players are told about the ships they hit.
+    #[serde(default)]
+    pub disclosure: Disclosure,
+}
+
+impl Rules {
+    pub fn standard(size: i32) -> Self {
+        Self {
+            players: [PlayerRules::standard(size), PlayerRules::standard(size)],
+            topology: Topology::Flat,
+            depths: 0,
+            armour: HashMap::new(),
+            repairs: 0,
+            hide_repairs: false,
+            reveal_decoys: false,
+            disclosure: Disclosure::Class,
+        }
+    }
+
+    pub fn player(&self, player: Player) -> &PlayerRules {
+        &self.players[player as usize]
+    }
+
+    pub fn armour_of(&self, class: ShipClass) -> u32 {
+        self.armour.get(&class).copied().unwrap_or(1)
+    }
+
+    /// Makes sure a game with these rules can be played to the end, mostly that every player's
+    /// ships can fit on their board. This only rules out fleets that can't possibly fit, so one
+    /// that passes can still be awkward to place.
+    pub fn check(&self) -> Result<(), RulesError> {
+        // checked first so that nothing below can overflow
+        if (self.players.iter()).any(|p| !(1..=MAX_SIZE).contains(&p.size)) {
+            return Err(RulesError::BadSize);
+        }
+        if !(0..=MAX_DEPTHS).contains(&self.depths) {
+            return Err(RulesError::TooDeep);
+        }
+        if let Some((&class, _)) = self.armour.iter().find(|(_, &hits)| hits == 0) {
+            return Err(RulesError::NoArmour(class));
+        }
+        for player in [Player::Player1, Player::Player2] {
+            let rules = self.player(player);
+            // with no ships to sink, the first shot would win
+            if rules.fleet.is_empty() {
+                return Err(RulesError::EmptyFleet(player.num()));
+            }
+            if rules.shots_per_turn == 0 {
+                return Err(RulesError::NoShots(player.num()));
+            }
+            let pieces = rules.pieces();
+            if let Some(&class) = pieces.iter().find(|c| c.size() > rules.size) {
+                return Err(RulesError::TooLong(player.num(), class));
+            }
+            // submarines have the layers below the surface to themselves
+            let cells = |submerged: bool| -> i32 {
+                (pieces.iter())
+                    .filter(|c| (*c.layers(self.depths).start() > 0) == submerged)
+                    .map(|c| c.size())
+                    .sum()
+            };
+            let area = rules.size * rules.size;
+            if cells(false) > area || cells(true) > area * self.depths {
+                return Err(RulesError::TooManyShips(player.num()));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    CreateShips,
+    PlayGame,
+    GameOver,
+}
+
 #[derive(Clone, Debug)]
 enum GameState {
-    CreateShips { grids: [Grid; 2] },
-    PlayGame { grids: [Grid; 2], turn: Player },
-    GameOver { grids: [Grid; 2] },
+    CreateShips {
+        grids: [Grid; 2],
+    },
+    PlayGame {
+        grids: [Grid; 2],
+        turn: Player,
+        // how many shots the player whose turn it is has fired so far this turn
+        shots: u32,
+    },
+    GameOver {
+        grids: [Grid; 2],
+    },
 }
 #[derive(Debug, Error)]
 pub enum GameAddShipError {
     #[error("too many ships on the board")]
     TooManyShips,
+    #[error("ships can only go in six directions on a hex board")]
+    NotHex,
+    #[error("ships on a hex board have to be placed with a hex direction")]
+    NotSquare,
+    #[error("submarines have to go below the surface, and other ships on it")]
+    WrongLayer,
     #[error(transparent)]
     Other(AddShipError),
 }
 #[derive(Debug, Error)]
+pub enum SetFleetError {
+    #[error("the layout has {given} ships, but {needed} still need placing")]
+    WrongCount { given: usize, needed: usize },
+    #[error("{} of the ships could not be placed", .0.len())]
+    Ships(Vec<(usize, GameAddShipError)>),
+}
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RulesError {
+    #[error("boards have to be between 1 and {MAX_SIZE} cells across")]
+    BadSize,
+    #[error("boards can have at most {MAX_DEPTHS} layers below the surface")]
+    TooDeep,
+    #[error("player {0} has no ships")]
+    EmptyFleet(usize),
+    #[error("player {0} has to fire at least one shot a turn")]
+    NoShots(usize),
+    #[error("player {0}'s {} is longer than their board", .1.name())]
+    TooLong(usize, ShipClass),
+    #[error("player {0}'s ships don't fit on their board")]
+    TooManyShips(usize),
+    #[error("{} cells have to take at least one hit", .0.name())]
+    NoArmour(ShipClass),
+}
+#[derive(Debug, Error)]
 pub enum StateOrOtherError<E> {
     #[error("the game is not in the correct state for this action")]
     WrongState,
@@ -64,26 +329,153 @@ pub enum GuessError {
     WrongPlayer,
 }
 
+#[derive(Debug, Error)]
+pub enum RepairError {
+    #[error("the game is not in the playing state")]
+    WrongState,
+    #[error("the wrong player has tried to repair")]
+    WrongPlayer,
+    #[error("repairs take a whole turn, so they can't be made after firing")]
+    TurnStarted,
+    #[error("there are no repairs left")]
+    NoRepairsLeft,
+    #[error("there is nothing there to repair")]
+    NotDamaged,
+}
+
 #[derive(Debug, Error)]
 pub enum CheckWinError {
     #[error("the game is not in the playing state")]
     WrongState,
 }
 
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TurnError {
+    #[error("the game is not in the playing state")]
+    WrongState,
+}
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("action {0} could not be applied to the game")]
+    InvalidAction(usize),
+}
+
+/// Everything a player can do that changes the game. Together with the seed these are enough to
+/// rebuild a game exactly, because anything random is drawn from the game's own RNG.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Action {
+    AddShip {
+        player: Player,
+        start: Location,
+        dir: Dir,
+    },
+    AddHexShip {
+        player: Player,
+        start: Location,
+        dir: HexDir,
+    },
+    AddRandomShips {
+        player: Player,
+    },
+    StartPlaying,
+    Guess {
+        player: Player,
+        loc: Location,
+    },
+    Repair {
+        player: Player,
+        loc: Location,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedGame {
+    pub seed: u64,
+    pub rules: Rules,
+    pub actions: Vec<Action>,
+}
+
 #[derive(Clone, Debug)]
 pub struct Game {
     state: GameState,
+    rules: Rules,
+    seed: u64,
+    rng: ChaCha8Rng,
+    actions: Vec<Action>,
 }
 
 impl Game {
     pub fn new(size: i32) -> Self {
+        Self::with_seed(size, rand::random())
+    }
+
+    pub fn with_seed(size: i32, seed: u64) -> Self {
+        Self::with_rules(Rules::standard(size), seed)
+    }
+
+    pub fn with_rules(rules: Rules, seed: u64) -> Self {
         Self {
             state: GameState::CreateShips {
-                grids: [Grid::new(size), Grid::new(size)],
+                grids: [
+                    Grid::with_topology(rules.players[0].size, rules.topology)
+                        .with_depths(rules.depths),
+                    Grid::with_topology(rules.players[1].size, rules.topology)
+                        .with_depths(rules.depths),
+                ],
             },
+            rules,
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            actions: vec![],
+        }
+    }
+
+    /// Rebuilds a game by applying every saved action, in order, to a new game with the same seed.
+    pub fn from_saved(saved: &SavedGame) -> Result<Self, ReplayError> {
+        let mut game = Self::with_rules(saved.rules.clone(), saved.seed);
+        for (i, action) in saved.actions.iter().enumerate() {
+            if !game.apply(action) {
+                return Err(ReplayError::InvalidAction(i));
+            }
+        }
+        Ok(game)
+    }
+
+    /// Does what `action` says, as if the player had just done it. Returns whether it worked.
+    pub fn apply(&mut self, action: &Action) -> bool {
+        match *action {
+            Action::AddShip { player, start, dir } => self.add_ship(player, start, dir).is_ok(),
+            Action::AddHexShip { player, start, dir } => {
+                (self.add_hex_ship(player, start.into(), start.layer, dir)).is_ok()
+            }
+            Action::AddRandomShips { player } => self.add_random_ships(player).is_ok(),
+            Action::StartPlaying => self.change_to_playing().is_ok(),
+            Action::Guess { player, loc } => self.guess_position(player, loc).is_ok(),
+            Action::Repair { player, loc } => self.repair(player, loc).is_ok(),
+        }
+    }
+
+    pub fn to_saved(&self) -> SavedGame {
+        SavedGame {
+            seed: self.seed,
+            rules: self.rules.clone(),
+            actions: self.actions.clone(),
         }
     }
 
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn actions(&self) -> &[Action] {
+        &self.actions
+    }
+
     pub fn get_grid(&self, player: Player) -> &Grid {
         match &self.state {
             GameState::CreateShips { grids } => &grids[player as usize],
@@ -92,33 +484,309 @@ impl Game {
         }
     }
 
-    pub fn get_turn(&self) -> Result<Player, ()> {
+    pub fn phase(&self) -> Phase {
         match self.state {
-            GameState::CreateShips { .. } => Err(()),
+            GameState::CreateShips { .. } => Phase::CreateShips,
+            GameState::PlayGame { .. } => Phase::PlayGame,
+            GameState::GameOver { .. } => Phase::GameOver,
+        }
+    }
+
+    /// The ships each player has to place, in the order they are placed.
+    pub fn fleet(&self, player: Player) -> &[ShipClass] {
+        &self.rules.player(player).fleet
+    }
+
+    /// The fleet followed by the player's decoys, which is the order everything is placed in.
+    pub fn pieces(&self, player: Player) -> Vec<ShipClass> {
+        self.rules.player(player).pieces()
+    }
+
+    /// Whether the player's next piece to place is a decoy.
+    pub fn placing_decoy(&self, player: Player) -> bool {
+        self.get_grid(player).ships.len() >= self.fleet(player).len()
+    }
+
+    /// What `player` can see of their opponent's grid.
+    pub fn view(&self, player: Player) -> PlayerView {
+        let other = player.other();
+        let grid = self.get_grid(other);
+        let mut view = PlayerView::of_grid(
+            grid,
+            &self.pieces(other),
+            self.rules.disclosure,
+            self.rules.reveal_decoys,
+        );
+        for loc in self.repairs_by(other) {
+            let Some(ship) = grid.ships.iter().find(|s| s.hit_points(loc).is_some()) else {
+                continue;
+            };
+            if ship.is_sunk() || ship.is_found(loc) || view.dented.contains(&loc) {
+                continue;
+            }
+            if self.rules.hide_repairs && self.destroyed_before_repair(player, loc) {
+                // as far as the player knows the cell is still destroyed
+                view.hits.push(loc);
+            } else {
+                // either they were told about the repair, or they only ever dented the cell and
+                // think the armour is holding
+                view.dented.push(loc);
+            }
+        }
+        view
+    }
+
+    /// Whether `player` had destroyed the cell at `loc` by the time it was repaired, going by
+    /// the shots they fired at it.
+    fn destroyed_before_repair(&self, player: Player, loc: Location) -> bool {
+        let Some(ship) =
+            (self.get_grid(player.other()).ships.iter()).find(|s| s.hit_points(loc).is_some())
+        else {
+            return false;
+        };
+        let mut hit_points = ship.armour();
+        for action in &self.actions {
+            match *action {
+                Action::Guess { player: p, loc: l } if p == player && l == loc => {
+                    hit_points = hit_points.saturating_sub(1);
+                }
+                Action::Repair { player: p, loc: l } if p != player && l == loc => {
+                    if hit_points == 0 {
+                        return true;
+                    }
+                    hit_points = ship.armour();
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// Where the player fired their most recent shot.
+    pub fn last_shot(&self, player: Player) -> Option<Location> {
+        self.actions.iter().rev().find_map(|a| match *a {
+            Action::Guess { player: p, loc } if p == player => Some(loc),
+            _ => None,
+        })
+    }
+
+    /// Every cell the player has repaired, in order.
+    pub fn repairs_by(&self, player: Player) -> Vec<Location> {
+        self.actions
+            .iter()
+            .filter_map(|a| match *a {
+                Action::Repair { player: p, loc } if p == player => Some(loc),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn repairs_left(&self, player: Player) -> u32 {
+        let used = self.repairs_by(player).len() as u32;
+        self.rules.repairs.saturating_sub(used)
+    }
+
+    pub fn winner(&self) -> Option<Player> {
+        match &self.state {
+            GameState::CreateShips { .. } => None,
+            GameState::PlayGame { grids, .. } | GameState::GameOver { grids } => {
+                if grids[0].check_loss() {
+                    Some(Player::Player2)
+                } else if grids[1].check_loss() {
+                    Some(Player::Player1)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// How many more shots the player whose turn it is gets this turn.
+    pub fn shots_left(&self) -> Option<u32> {
+        match self.state {
+            GameState::PlayGame { turn, shots, .. } => {
+                Some(self.rules.player(turn).shots_per_turn.max(1) - shots)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn get_turn(&self) -> Result<Player, TurnError> {
+        match self.state {
+            GameState::CreateShips { .. } => Err(TurnError::WrongState),
             GameState::PlayGame { turn, .. } => Ok(turn),
-            GameState::GameOver { .. } => Err(()),
+            GameState::GameOver { .. } => Err(TurnError::WrongState),
         }
     }
 
+    /// Adds the player's next ship, going from `start_point` in `ship_dir`. Hex boards need
+    /// `add_hex_ship` instead.
     pub fn add_ship(
         &mut self,
         player: Player,
         start_point: Location,
-        ship_dir: ShipDir,
+        ship_dir: impl Into<Dir>,
+    ) -> Result<(), StateOrOtherError<GameAddShipError>> {
+        if self.rules.topology == Topology::Hex {
+            return Err(StateOrOtherError::Other(GameAddShipError::NotSquare));
+        }
+        let ship_dir = ship_dir.into();
+        let action = Action::AddShip {
+            player,
+            start: start_point,
+            dir: ship_dir,
+        };
+        self.place_ship(player, action, |grid, len| {
+            grid.new_ship(start_point, ship_dir, len)
+        })
+    }
+
+    /// Adds a ship going in any of the six directions, on `layer` if the board has depths. Only
+    /// works on hex boards.
+    pub fn add_hex_ship(
+        &mut self,
+        player: Player,
+        start: HexCoord,
+        layer: i32,
+        dir: HexDir,
+    ) -> Result<(), StateOrOtherError<GameAddShipError>> {
+        if self.rules.topology != Topology::Hex {
+            return Err(StateOrOtherError::Other(GameAddShipError::NotHex));
+        }
+        let action = Action::AddHexShip {
+            player,
+            start: Location::from(start).with_layer(layer),
+            dir,
+        };
+        self.place_ship(player, action, |_, len| {
+            Ship::new_hex(start, dir, len).on_layer(layer)
+        })
+    }
+
+    /// Adds the player's next ship, made by `make` from the grid and the ship's length.
+    fn place_ship(
+        &mut self,
+        player: Player,
+        action: Action,
+        make: impl FnOnce(&Grid, i32) -> Ship,
     ) -> Result<(), StateOrOtherError<GameAddShipError>> {
         match &mut self.state {
             GameState::CreateShips { grids } => {
                 let count = grids[player as usize].ships.len();
 
-                let size = *SHIPS_ORDER
+                let rules = self.rules.player(player);
+                let class = *rules
+                    .pieces()
                     .get(count)
                     .ok_or(StateOrOtherError::Other(GameAddShipError::TooManyShips))?;
 
-                let ship = Ship::new(start_point, ship_dir, size);
-                grids[player as usize]
-                    .add_ship(ship)
+                let grid = &mut grids[player as usize];
+                let ship = make(grid, class.size());
+                let ship =
+                    outfit(&self.rules, player, count, ship).map_err(StateOrOtherError::Other)?;
+                grid.add_ship(ship)
                     .map_err(GameAddShipError::Other)
-                    .map_err(StateOrOtherError::Other)
+                    .map_err(StateOrOtherError::Other)?;
+                self.actions.push(action);
+                Ok(())
+            }
+            _ => Err(StateOrOtherError::WrongState),
+        }
+    }
+
+    /// Places all of the player's remaining ships at random using the game's RNG.
+    pub fn add_random_ships(
+        &mut self,
+        player: Player,
+    ) -> Result<(), StateOrOtherError<GameAddShipError>> {
+        match &mut self.state {
+            GameState::CreateShips { grids } => {
+                // work on a copy so that the grid is left untouched if the fleet doesn't fit
+                let mut grid = grids[player as usize].clone();
+                let rules = self.rules.player(player);
+                for (i, class) in rules.pieces().into_iter().enumerate() {
+                    if i < grid.ships.len() {
+                        continue;
+                    }
+                    let layers = class.layers(self.rules.depths);
+                    grid.add_random_ship_on(class.size(), layers, &mut self.rng)
+                        .map_err(GameAddShipError::Other)
+                        .map_err(StateOrOtherError::Other)?;
+                    let ship = grid.ships.last_mut().unwrap();
+                    ship.set_armour(self.rules.armour_of(class));
+                    ship.set_decoy(i >= rules.fleet.len());
+                }
+                grids[player as usize] = grid;
+                self.actions.push(Action::AddRandomShips { player });
+                Ok(())
+            }
+            _ => Err(StateOrOtherError::WrongState),
+        }
+    }
+
+    /// Places every ship the player still has to place in one go, each from its first cell and
+    /// direction. Nothing is placed unless the whole layout is valid, and no layout is valid on a
+    /// hex board.
+    pub fn set_fleet(
+        &mut self,
+        player: Player,
+        layout: &[(Location, Dir)],
+    ) -> Result<(), StateOrOtherError<SetFleetError>> {
+        match &mut self.state {
+            GameState::CreateShips { grids } => {
+                let grid = &grids[player as usize];
+                let placed = grid.ships.len();
+                let pieces = self.rules.player(player).pieces();
+                let needed = pieces.len().saturating_sub(placed);
+                if layout.len() != needed {
+                    return Err(StateOrOtherError::Other(SetFleetError::WrongCount {
+                        given: layout.len(),
+                        needed,
+                    }));
+                }
+
+                if self.rules.topology == Topology::Hex {
+                    let errors = (0..layout.len())
+                        .map(|i| (i, GameAddShipError::NotSquare))
+                        .collect();
+                    return Err(StateOrOtherError::Other(SetFleetError::Ships(errors)));
+                }
+
+                let mut errors = vec![];
+                let mut ships = vec![];
+                for (i, (&(start, dir), class)) in layout.iter().zip(&pieces[placed..]).enumerate()
+                {
+                    let ship = grid.new_ship(start, dir, class.size());
+                    match outfit(&self.rules, player, placed + i, ship.clone()) {
+                        Ok(ship) => ships.push(ship),
+                        Err(e) => {
+                            errors.push((i, e));
+                            // still check it against the others so every problem gets reported
+                            ships.push(ship);
+                        }
+                    }
+                }
+                let mut new_grid = grid.clone();
+                if let Err(PlaceFleetError(bad)) = new_grid.place_fleet(ships) {
+                    for (i, e) in bad {
+                        if !errors.iter().any(|(j, _)| *j == i) {
+                            errors.push((i, GameAddShipError::Other(e)));
+                        }
+                    }
+                    errors.sort_by_key(|(i, _)| *i);
+                }
+                if !errors.is_empty() {
+                    return Err(StateOrOtherError::Other(SetFleetError::Ships(errors)));
+                }
+
+                grids[player as usize] = new_grid;
+                self.actions
+                    .extend(layout.iter().map(|&(start, dir)| Action::AddShip {
+                        player,
+                        start,
+                        dir,
+                    }));
+                Ok(())
             }
             _ => Err(StateOrOtherError::WrongState),
         }
@@ -127,53 +795,764 @@ impl Game {
     pub fn change_to_playing(&mut self) -> Result<(), ChangeToPlayingError> {
         match &mut self.state {
             GameState::CreateShips { grids } => {
-                if grids[0].ships.len() < SHIPS_ORDER.len()
-                    || grids[1].ships.len() < SHIPS_ORDER.len()
+                if grids[0].ships.len() < self.rules.players[0].pieces().len()
+                    || grids[1].ships.len() < self.rules.players[1].pieces().len()
                 {
                     return Err(ChangeToPlayingError::NotEnoughShips);
                 }
                 self.state = GameState::PlayGame {
                     grids: grids.clone(),
                     turn: Player::Player1,
+                    shots: 0,
                 };
+                self.actions.push(Action::StartPlaying);
                 Ok(())
             }
             _ => Err(ChangeToPlayingError::WrongState),
         }
     }
 
-    pub fn guess_position(&mut self, player: Player, coords: Location) -> Result<bool, GuessError> {
+    pub fn guess_position(
+        &mut self,
+        player: Player,
+        coords: Location,
+    ) -> Result<ShotOutcome, GuessError> {
         match &mut self.state {
-            GameState::PlayGame { grids, turn } => {
+            GameState::PlayGame { grids, turn, shots } => {
                 if player != *turn {
                     return Err(GuessError::WrongPlayer);
                 }
-                let result = grids[1 - *turn as usize].guess_grid(coords);
-                *turn = match player {
-                    Player::Player1 => Player::Player2,
-                    Player::Player2 => Player::Player1,
-                };
+                let other = player.other();
+                let result = ShotOutcome::fire(
+                    &mut grids[other as usize],
+                    coords,
+                    &self.rules.player(other).pieces(),
+                    self.rules.reveal_decoys,
+                );
+                let result = self.rules.disclosure.disclose(result);
+                *shots += 1;
+                if *shots >= self.rules.player(player).shots_per_turn {
+                    *turn = other;
+                    *shots = 0;
+                }
+                self.actions.push(Action::Guess {
+                    player,
+                    loc: coords,
+                });
                 Ok(result)
             }
             _ => Err(GuessError::WrongState),
         }
     }
 
+    /// Spends the player's whole turn repairing one damaged cell of one of their own ships.
+    pub fn repair(&mut self, player: Player, coords: Location) -> Result<(), RepairError> {
+        let repairs_left = self.repairs_left(player);
+        match &mut self.state {
+            GameState::PlayGame { grids, turn, shots } => {
+                if player != *turn {
+                    return Err(RepairError::WrongPlayer);
+                }
+                if *shots > 0 {
+                    return Err(RepairError::TurnStarted);
+                }
+                if repairs_left == 0 {
+                    return Err(RepairError::NoRepairsLeft);
+                }
+                if !grids[player as usize].repair(coords) {
+                    return Err(RepairError::NotDamaged);
+                }
+                *turn = player.other();
+                self.actions.push(Action::Repair {
+                    player,
+                    loc: coords,
+                });
+                Ok(())
+            }
+            _ => Err(RepairError::WrongState),
+        }
+    }
+
     pub fn check_if_win(&mut self) -> Result<Option<Player>, CheckWinError> {
         match &self.state {
             GameState::PlayGame { grids, .. } => {
-                if grids[0].get_all_found().len() == grids[0].get_all().len() {
+                let winner = self.winner();
+                if winner.is_some() {
                     self.state = GameState::GameOver {
                         grids: grids.clone(),
                     };
-                    Ok(Some(Player::Player2))
-                } else if grids[1].get_all_found().len() == grids[1].get_all().len() {
-                    Ok(Some(Player::Player1))
-                } else {
-                    Ok(None)
                 }
+                Ok(winner)
             }
             _ => Err(CheckWinError::WrongState),
         }
     }
 }
+
+/// Makes `ship` into the player's piece number `index`, with its armour and whether it's a decoy,
+/// as long as it's on a layer its class can go on.
+fn outfit(
+    rules: &Rules,
+    player: Player,
+    index: usize,
+    mut ship: Ship,
+) -> Result<Ship, GameAddShipError> {
+    let player_rules = rules.player(player);
+    let class = player_rules.pieces()[index];
+    ship.set_armour(rules.armour_of(class));
+    ship.set_decoy(index >= player_rules.fleet.len());
+    let layer = ship.get_coords()[0].layer;
+    if !class.layers(rules.depths).contains(&layer) {
+        return Err(GameAddShipError::WrongLayer);
+    }
+    Ok(ship)
+}
+
+/// A game for tests, with each player's ships drawn in the `Grid` text format and matched to
+/// `fleet` in letter order, after `shots` (like `"B7"`) have been fired in turn from player 1.
+#[cfg(test)]
+pub(crate) fn test_game(fleet: &[ShipClass], boards: [&str; 2], shots: &[&str]) -> Game {
+    let grids = boards.map(|b| b.parse::<Grid>().unwrap());
+    let mut rules = Rules::standard(grids[0].size);
+    for p in &mut rules.players {
+        p.fleet = fleet.to_vec();
+    }
+    let mut game = Game::with_rules(rules, 1);
+    for (player, grid) in [Player::Player1, Player::Player2].into_iter().zip(grids) {
+        let layout: Vec<_> = (grid.ships.iter())
+            .map(|ship| {
+                let coords = ship.get_coords();
+                // going up is towards bigger y, which is down the drawing
+                let down = coords.len() > 1 && coords[0].x == coords[1].x;
+                (coords[0], if down { Dir::Up } else { Dir::Right })
+            })
+            .collect();
+        game.set_fleet(player, &layout).unwrap();
+    }
+    game.change_to_playing().unwrap();
+    let turns = [Player::Player1, Player::Player2].into_iter().cycle();
+    for (shot, player) in shots.iter().zip(turns) {
+        game.guess_position(player, shot.parse().unwrap()).unwrap();
+    }
+    game.check_if_win().unwrap();
+    game
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ship::ShipDir;
+
+    #[test]
+    fn test_random_ships_same_seed() {
+        let mut game1 = Game::with_seed(10, 1234);
+        let mut game2 = Game::with_seed(10, 1234);
+        for p in [Player::Player1, Player::Player2] {
+            game1.add_random_ships(p).unwrap();
+            game2.add_random_ships(p).unwrap();
+            assert_eq!(game1.get_grid(p), game2.get_grid(p));
+            assert_eq!(game1.get_grid(p).ships.len(), SHIPS_ORDER.len());
+        }
+        assert_ne!(
+            game1.get_grid(Player::Player1),
+            game1.get_grid(Player::Player2)
+        );
+    }
+
+    #[test]
+    fn test_replay_saved_game() {
+        let mut game = Game::with_seed(10, 99);
+        game.add_random_ships(Player::Player1).unwrap();
+        for y in 0..5 {
+            game.add_ship(Player::Player2, Location::new(0, y), ShipDir::Horz)
+                .unwrap();
+        }
+        // an invalid action doesn't change the game, so it isn't recorded
+        assert!(game
+            .add_ship(Player::Player2, Location::new(0, 0), ShipDir::Horz)
+            .is_err());
+        game.change_to_playing().unwrap();
+        game.guess_position(Player::Player1, Location::new(0, 0))
+            .unwrap();
+        game.guess_position(Player::Player2, Location::new(3, 3))
+            .unwrap();
+
+        let saved = game.to_saved();
+        assert_eq!(saved.seed, 99);
+        assert_eq!(saved.actions.len(), 9);
+
+        let json = serde_json::to_string(&saved).unwrap();
+        let loaded: SavedGame = serde_json::from_str(&json).unwrap();
+        let replayed = Game::from_saved(&loaded).unwrap();
+        for p in [Player::Player1, Player::Player2] {
+            assert_eq!(replayed.get_grid(p), game.get_grid(p));
+        }
+        assert_eq!(replayed.get_turn(), game.get_turn());
+        assert_eq!(replayed.to_saved(), saved);
+    }
+
+    #[test]
+    fn test_handicap_rules() {
+        let mut rules = Rules::standard(10);
+        rules.players[1] = PlayerRules {
+            size: 6,
+            fleet: vec![ShipClass::Destroyer, ShipClass::Submarine],
+            shots_per_turn: 2,
+            decoys: vec![],
+        };
+        let mut game = Game::with_rules(rules, 5);
+        game.add_random_ships(Player::Player1).unwrap();
+        game.add_ship(Player::Player2, Location::new(0, 0), ShipDir::Horz)
+            .unwrap();
+        game.add_ship(Player::Player2, Location::new(0, 5), ShipDir::Horz)
+            .unwrap();
+        // player 2's fleet is already complete
+        assert!(game
+            .add_ship(Player::Player2, Location::new(0, 3), ShipDir::Horz)
+            .is_err());
+        assert_eq!(game.get_grid(Player::Player2).size, 6);
+        game.change_to_playing().unwrap();
+
+        // player 1 fires once, then player 2 gets two shots in a row
+        assert_eq!(game.shots_left(), Some(1));
+        game.guess_position(Player::Player1, Location::new(5, 5))
+            .unwrap();
+        assert_eq!(game.get_turn(), Ok(Player::Player2));
+        assert_eq!(game.shots_left(), Some(2));
+        game.guess_position(Player::Player2, Location::new(9, 9))
+            .unwrap();
+        assert_eq!(game.get_turn(), Ok(Player::Player2));
+        assert_eq!(game.shots_left(), Some(1));
+        game.guess_position(Player::Player2, Location::new(9, 8))
+            .unwrap();
+        assert_eq!(game.get_turn(), Ok(Player::Player1));
+
+        // player 1 only has to sink the two ships player 2 was given
+        for (x, y) in [(0, 0), (1, 0), (0, 5), (1, 5)] {
+            while game.get_turn() == Ok(Player::Player2) {
+                game.guess_position(Player::Player2, Location::new(9, 0))
+                    .unwrap();
+            }
+            game.guess_position(Player::Player1, Location::new(x, y))
+                .unwrap();
+        }
+        assert_eq!(game.winner(), None);
+        while game.get_turn() == Ok(Player::Player2) {
+            game.guess_position(Player::Player2, Location::new(9, 0))
+                .unwrap();
+        }
+        game.guess_position(Player::Player1, Location::new(2, 5))
+            .unwrap();
+        assert_eq!(game.winner(), Some(Player::Player1));
+    }
+
+    #[test]
+    fn test_rules_check() {
+        let mut rules = Rules::standard(10);
+        assert_eq!(rules.check(), Ok(()));
+        rules.players[0].size = 4;
+        assert_eq!(
+            rules.check(),
+            Err(RulesError::TooLong(1, ShipClass::Carrier))
+        );
+        // the ships are all short enough, but there are too many of them
+        rules.players[0].fleet = vec![ShipClass::Battleship; 5];
+        assert_eq!(rules.check(), Err(RulesError::TooManyShips(1)));
+        rules.players[0].fleet = vec![ShipClass::Battleship; 4];
+        assert_eq!(rules.check(), Ok(()));
+        rules.players[1].size = 3;
+        rules.players[1].fleet = vec![ShipClass::Destroyer];
+        rules.players[1].decoys = vec![ShipClass::Carrier];
+        assert_eq!(
+            rules.check(),
+            Err(RulesError::TooLong(2, ShipClass::Carrier))
+        );
+
+        // submarines get the layers below the surface to themselves
+        let mut rules = Rules::standard(3);
+        rules.players[1].fleet = vec![ShipClass::Cruiser; 3];
+        rules.players[0].fleet = vec![ShipClass::Submarine; 4];
+        assert_eq!(rules.check(), Err(RulesError::TooManyShips(1)));
+        rules.depths = 1;
+        assert_eq!(rules.check(), Err(RulesError::TooManyShips(1)));
+        rules.depths = 2;
+        assert_eq!(rules.check(), Ok(()));
+
+        let mut rules = Rules::standard(10);
+        rules.players[1].fleet = vec![];
+        rules.players[1].decoys = vec![ShipClass::Cruiser];
+        assert_eq!(rules.check(), Err(RulesError::EmptyFleet(2)));
+        let mut rules = Rules::standard(10);
+        rules.players[0].shots_per_turn = 0;
+        assert_eq!(rules.check(), Err(RulesError::NoShots(1)));
+        let mut rules = Rules::standard(10);
+        rules.armour.insert(ShipClass::Carrier, 0);
+        assert_eq!(rules.check(), Err(RulesError::NoArmour(ShipClass::Carrier)));
+
+        assert_eq!(Rules::standard(0).check(), Err(RulesError::BadSize));
+        assert_eq!(Rules::standard(i32::MAX).check(), Err(RulesError::BadSize));
+        let mut rules = Rules::standard(10);
+        rules.depths = MAX_DEPTHS + 1;
+        assert_eq!(rules.check(), Err(RulesError::TooDeep));
+    }
+
+    #[test]
+    fn test_bow_placement() {
+        let mut game = Game::new(10);
+        // the carrier goes last, so drag it out to the left from the far edge
+        for (x, y, dir) in [
+            (9, 0, Dir::Left),
+            (0, 2, Dir::Right),
+            (0, 4, Dir::Up),
+            (2, 8, Dir::Down),
+        ] {
+            game.add_ship(Player::Player1, Location::new(x, y), dir)
+                .unwrap();
+        }
+        assert!(matches!(
+            game.add_ship(Player::Player1, Location::new(3, 9), Dir::Left),
+            Err(StateOrOtherError::Other(GameAddShipError::Other(
+                AddShipError::ShipOutOfBounds
+            )))
+        ));
+        game.add_ship(Player::Player1, Location::new(4, 9), Dir::Left)
+            .unwrap();
+        let carrier = &game.get_grid(Player::Player1).ships[4];
+        assert_eq!(carrier.get_coords()[4], Location::new(0, 9));
+
+        game.add_random_ships(Player::Player2).unwrap();
+        game.change_to_playing().unwrap();
+        let replayed = Game::from_saved(&game.to_saved()).unwrap();
+        assert_eq!(
+            replayed.get_grid(Player::Player1),
+            game.get_grid(Player::Player1)
+        );
+    }
+
+    #[test]
+    fn test_torus_ships_wrap() {
+        let mut rules = Rules::standard(10);
+        rules.topology = Topology::Torus;
+        let mut game = Game::with_rules(rules, 8);
+        game.add_ship(Player::Player1, Location::new(9, 0), ShipDir::Horz)
+            .unwrap();
+        assert_eq!(
+            game.get_grid(Player::Player1).ships[0].get_coords(),
+            [Location::new(9, 0), Location::new(0, 0)]
+        );
+        game.add_random_ships(Player::Player1).unwrap();
+        game.add_random_ships(Player::Player2).unwrap();
+
+        let replayed = Game::from_saved(&game.to_saved()).unwrap();
+        assert_eq!(
+            replayed.get_grid(Player::Player1),
+            game.get_grid(Player::Player1)
+        );
+    }
+
+    #[test]
+    fn test_hex_ships() {
+        let mut game = Game::with_seed(10, 2);
+        assert!(matches!(
+            game.add_hex_ship(Player::Player1, HexCoord::new(0, 0), 0, HexDir::East),
+            Err(StateOrOtherError::Other(GameAddShipError::NotHex))
+        ));
+
+        let mut rules = Rules::standard(6);
+        rules.topology = Topology::Hex;
+        let mut game = Game::with_rules(rules, 2);
+        game.add_hex_ship(Player::Player1, HexCoord::new(3, 0), 0, HexDir::SouthWest)
+            .unwrap();
+        assert_eq!(
+            game.get_grid(Player::Player1).ships[0].get_coords(),
+            [Location::new(3, 0), Location::new(2, 1)]
+        );
+        // going north off the top of the board
+        assert!(game
+            .add_hex_ship(Player::Player1, HexCoord::new(0, 0), 0, HexDir::NorthWest)
+            .is_err());
+        // square directions don't line up with the hexes
+        assert!(matches!(
+            game.add_ship(Player::Player1, Location::new(0, 3), Dir::Up),
+            Err(StateOrOtherError::Other(GameAddShipError::NotSquare))
+        ));
+        assert!(matches!(
+            game.set_fleet(Player::Player1, &[(Location::new(0, 3), Dir::Right); 4]),
+            Err(StateOrOtherError::Other(SetFleetError::Ships(errors))) if errors.len() == 4
+        ));
+        game.add_random_ships(Player::Player1).unwrap();
+
+        let replayed = Game::from_saved(&game.to_saved()).unwrap();
+        assert_eq!(
+            replayed.get_grid(Player::Player1),
+            game.get_grid(Player::Player1)
+        );
+    }
+
+    #[test]
+    fn test_hex_ships_with_depths() {
+        let mut rules = Rules::standard(6);
+        rules.topology = Topology::Hex;
+        rules.depths = 2;
+        let mut game = Game::with_rules(rules, 3);
+        game.add_hex_ship(Player::Player1, HexCoord::new(0, 0), 0, HexDir::East)
+            .unwrap();
+        assert!(matches!(
+            game.add_hex_ship(Player::Player1, HexCoord::new(0, 1), 0, HexDir::East),
+            Err(StateOrOtherError::Other(GameAddShipError::WrongLayer))
+        ));
+        game.add_hex_ship(Player::Player1, HexCoord::new(0, 1), 2, HexDir::East)
+            .unwrap();
+        assert_eq!(
+            game.get_grid(Player::Player1).ships[1].get_coords(),
+            [
+                Location::at_depth(0, 1, 2),
+                Location::at_depth(1, 1, 2),
+                Location::at_depth(2, 1, 2)
+            ]
+        );
+
+        let replayed = Game::from_saved(&game.to_saved()).unwrap();
+        assert_eq!(
+            replayed.get_grid(Player::Player1),
+            game.get_grid(Player::Player1)
+        );
+    }
+
+    #[test]
+    fn test_depth_charges() {
+        let mut rules = Rules::standard(10);
+        rules.depths = 2;
+        let mut game = Game::with_rules(rules, 6);
+        // the destroyer can't dive
+        assert!(matches!(
+            game.add_ship(Player::Player1, Location::at_depth(0, 0, 1), ShipDir::Horz),
+            Err(StateOrOtherError::Other(GameAddShipError::WrongLayer))
+        ));
+        game.add_ship(Player::Player1, Location::new(0, 0), ShipDir::Horz)
+            .unwrap();
+        // and the submarine can't surface
+        assert!(matches!(
+            game.add_ship(Player::Player1, Location::new(0, 2), ShipDir::Horz),
+            Err(StateOrOtherError::Other(GameAddShipError::WrongLayer))
+        ));
+        game.add_ship(Player::Player1, Location::at_depth(0, 0, 2), ShipDir::Horz)
+            .unwrap();
+        game.add_random_ships(Player::Player1).unwrap();
+        game.add_random_ships(Player::Player2).unwrap();
+        let p2_sub = &game.get_grid(Player::Player2).ships[1];
+        assert!(p2_sub.get_coords()[0].layer >= 1);
+        game.change_to_playing().unwrap();
+
+        game.guess_position(Player::Player1, Location::new(9, 9))
+            .unwrap();
+        // a shot on the surface passes over the submarine
+        assert_eq!(
+            game.guess_position(Player::Player2, Location::new(2, 0))
+                .unwrap(),
+            ShotOutcome::Miss
+        );
+        game.guess_position(Player::Player1, Location::new(9, 8))
+            .unwrap();
+        assert_eq!(
+            game.guess_position(Player::Player2, Location::at_depth(2, 0, 2))
+                .unwrap(),
+            ShotOutcome::Hit
+        );
+    }
+
+    #[test]
+    fn test_armoured_ships() {
+        let mut rules = Rules::standard(10);
+        rules.players[1].fleet = vec![ShipClass::Destroyer];
+        rules.armour.insert(ShipClass::Destroyer, 2);
+        let mut game = Game::with_rules(rules, 1);
+        game.add_random_ships(Player::Player1).unwrap();
+        game.add_ship(Player::Player2, Location::new(0, 0), ShipDir::Horz)
+            .unwrap();
+        game.change_to_playing().unwrap();
+
+        let mut outcomes = vec![];
+        for loc in [(0, 0), (0, 0), (1, 0), (1, 0)] {
+            outcomes.push(
+                game.guess_position(Player::Player1, Location::new(loc.0, loc.1))
+                    .unwrap(),
+            );
+            if game.check_if_win().unwrap().is_none() {
+                game.guess_position(Player::Player2, Location::new(9, 9))
+                    .unwrap();
+            }
+        }
+        assert_eq!(
+            outcomes,
+            vec![
+                ShotOutcome::Armoured,
+                ShotOutcome::Hit,
+                ShotOutcome::Armoured,
+                ShotOutcome::Sunk(ShipClass::Destroyer)
+            ]
+        );
+        assert_eq!(game.winner(), Some(Player::Player1));
+    }
+
+    #[test]
+    fn test_set_fleet() {
+        let mut game = Game::new(10);
+        game.add_ship(Player::Player1, Location::new(0, 0), ShipDir::Horz)
+            .unwrap();
+        assert!(matches!(
+            game.set_fleet(Player::Player1, &[(Location::new(0, 2), Dir::Right)]),
+            Err(StateOrOtherError::Other(SetFleetError::WrongCount {
+                given: 1,
+                needed: 4
+            }))
+        ));
+
+        let mut layout: Vec<_> = (1..5)
+            .map(|y| (Location::new(0, y * 2), Dir::Right))
+            .collect();
+        layout[1].0 = Location::new(0, 0);
+        layout[3].0 = Location::new(8, 8);
+        let Err(StateOrOtherError::Other(SetFleetError::Ships(errors))) =
+            game.set_fleet(Player::Player1, &layout)
+        else {
+            panic!("the layout should have been rejected");
+        };
+        let bad: Vec<usize> = errors.iter().map(|(i, _)| *i).collect();
+        assert_eq!(bad, vec![1, 3]);
+        assert_eq!(game.get_grid(Player::Player1).ships.len(), 1);
+
+        layout[1].0 = Location::new(0, 4);
+        layout[3].0 = Location::new(0, 8);
+        game.set_fleet(Player::Player1, &layout).unwrap();
+        game.add_random_ships(Player::Player2).unwrap();
+        game.change_to_playing().unwrap();
+
+        // placing a fleet replays like placing each ship
+        let replayed = Game::from_saved(&game.to_saved()).unwrap();
+        assert_eq!(
+            replayed.get_grid(Player::Player1),
+            game.get_grid(Player::Player1)
+        );
+    }
+
+    fn decoy_game(reveal_decoys: bool) -> Game {
+        let mut rules = Rules::standard(10);
+        rules.players[1].fleet = vec![ShipClass::Destroyer];
+        rules.players[1].decoys = vec![ShipClass::Destroyer];
+        rules.reveal_decoys = reveal_decoys;
+        let mut game = Game::with_rules(rules, 5);
+        game.add_random_ships(Player::Player1).unwrap();
+        game.add_ship(Player::Player2, Location::new(0, 0), ShipDir::Horz)
+            .unwrap();
+        // the decoy still has to be placed
+        assert!(game.placing_decoy(Player::Player2));
+        assert!(matches!(
+            game.change_to_playing(),
+            Err(ChangeToPlayingError::NotEnoughShips)
+        ));
+        game.add_ship(Player::Player2, Location::new(0, 2), ShipDir::Horz)
+            .unwrap();
+        assert!(matches!(
+            game.add_ship(Player::Player2, Location::new(0, 4), ShipDir::Horz),
+            Err(StateOrOtherError::Other(GameAddShipError::TooManyShips))
+        ));
+        game.change_to_playing().unwrap();
+        game
+    }
+
+    /// Has player 1 shoot at each of `locs` in turn, with player 2 shooting into the sea.
+    fn shoot_all(game: &mut Game, locs: &[(i32, i32)]) -> Vec<ShotOutcome> {
+        let mut outcomes = vec![];
+        for &(x, y) in locs {
+            outcomes.push(
+                game.guess_position(Player::Player1, Location::new(x, y))
+                    .unwrap(),
+            );
+            if game.check_if_win().unwrap().is_none() {
+                game.guess_position(Player::Player2, Location::new(9, 9))
+                    .unwrap();
+            }
+        }
+        outcomes
+    }
+
+    #[test]
+    fn test_decoys() {
+        let mut game = decoy_game(false);
+        let outcomes = shoot_all(&mut game, &[(0, 2), (1, 2)]);
+        // the decoy passes for a real destroyer
+        assert_eq!(
+            outcomes,
+            vec![ShotOutcome::Hit, ShotOutcome::Sunk(ShipClass::Destroyer)]
+        );
+        let view = game.view(Player::Player1);
+        assert!(view.is_sunk_at(Location::new(0, 2)));
+        assert!(view.decoys.is_empty());
+        assert_eq!(view.remaining, vec![ShipClass::Destroyer]);
+        assert_eq!(game.winner(), None);
+
+        shoot_all(&mut game, &[(0, 0), (1, 0)]);
+        assert_eq!(game.winner(), Some(Player::Player1));
+    }
+
+    #[test]
+    fn test_revealed_decoys() {
+        let mut game = decoy_game(true);
+        let outcomes = shoot_all(&mut game, &[(0, 2), (1, 2)]);
+        assert_eq!(outcomes, vec![ShotOutcome::Hit, ShotOutcome::Decoy]);
+        let view = game.view(Player::Player1);
+        assert!(view.sunk.is_empty());
+        assert_eq!(
+            view.decoys,
+            vec![vec![Location::new(0, 2), Location::new(1, 2)]]
+        );
+
+        // the decoy doesn't need to be found for the game to end
+        let mut game = decoy_game(true);
+        shoot_all(&mut game, &[(0, 0), (1, 0)]);
+        assert_eq!(game.winner(), Some(Player::Player1));
+    }
+
+    #[test]
+    fn test_disclosure() {
+        for (disclosure, sunk) in [
+            (Disclosure::Class, ShotOutcome::Sunk(ShipClass::Destroyer)),
+            (Disclosure::Sunk, ShotOutcome::SunkUnnamed),
+            (Disclosure::HitOrMiss, ShotOutcome::Hit),
+        ] {
+            let mut rules = Rules::standard(10);
+            rules.players[1].fleet = vec![ShipClass::Destroyer, ShipClass::Cruiser];
+            rules.disclosure = disclosure;
+            let mut game = Game::with_rules(rules, 2);
+            game.add_random_ships(Player::Player1).unwrap();
+            game.add_ship(Player::Player2, Location::new(0, 0), ShipDir::Horz)
+                .unwrap();
+            game.add_ship(Player::Player2, Location::new(0, 2), ShipDir::Horz)
+                .unwrap();
+            game.change_to_playing().unwrap();
+
+            let outcomes = shoot_all(&mut game, &[(0, 0), (1, 0)]);
+            assert_eq!(outcomes, vec![ShotOutcome::Hit, sunk]);
+            let view = game.view(Player::Player1);
+            match disclosure {
+                Disclosure::Class => assert_eq!(view.sunk[0].0, Some(ShipClass::Destroyer)),
+                Disclosure::Sunk => assert_eq!(view.sunk[0].0, None),
+                Disclosure::HitOrMiss => {
+                    assert!(view.sunk.is_empty());
+                    assert_eq!(view.hits.len(), 2);
+                }
+            }
+            // the grid itself still knows the destroyer is gone
+            assert!(game.get_grid(Player::Player2).ships[0].is_sunk());
+        }
+    }
+
+    fn repair_game(hide_repairs: bool) -> Game {
+        let mut rules = Rules::standard(10);
+        rules.repairs = 1;
+        rules.hide_repairs = hide_repairs;
+        let mut game = Game::with_rules(rules, 3);
+        for p in [Player::Player1, Player::Player2] {
+            for y in 0..5 {
+                game.add_ship(p, Location::new(0, y * 2), ShipDir::Horz)
+                    .unwrap();
+            }
+        }
+        game.change_to_playing().unwrap();
+        game
+    }
+
+    #[test]
+    fn test_repairs() {
+        let mut game = repair_game(false);
+        game.guess_position(Player::Player1, Location::new(0, 2))
+            .unwrap();
+        assert!(matches!(
+            game.repair(Player::Player2, Location::new(5, 5)),
+            Err(RepairError::NotDamaged)
+        ));
+        game.repair(Player::Player2, Location::new(0, 2)).unwrap();
+        assert_eq!(game.get_turn(), Ok(Player::Player1));
+        assert_eq!(game.repairs_left(Player::Player2), 0);
+        // player 1 was told, so they know there's a ship there to shoot again
+        let view = game.view(Player::Player1);
+        assert_eq!(view.hits, vec![]);
+        assert_eq!(view.dented, vec![Location::new(0, 2)]);
+
+        game.guess_position(Player::Player1, Location::new(0, 2))
+            .unwrap();
+        assert!(matches!(
+            game.repair(Player::Player2, Location::new(0, 2)),
+            Err(RepairError::NoRepairsLeft)
+        ));
+
+        let replayed = Game::from_saved(&game.to_saved()).unwrap();
+        assert_eq!(
+            replayed.get_grid(Player::Player2),
+            game.get_grid(Player::Player2)
+        );
+    }
+
+    #[test]
+    fn test_hidden_repairs() {
+        let mut game = repair_game(true);
+        game.guess_position(Player::Player1, Location::new(0, 2))
+            .unwrap();
+        game.repair(Player::Player2, Location::new(0, 2)).unwrap();
+        assert!(game.get_grid(Player::Player2).get_all_found().is_empty());
+        // player 1 still thinks the cell is destroyed
+        assert_eq!(game.view(Player::Player1).hits, vec![Location::new(0, 2)]);
+    }
+
+    #[test]
+    fn test_hidden_repairs_with_armour() {
+        let mut rules = Rules::standard(10);
+        rules.repairs = 2;
+        rules.hide_repairs = true;
+        rules.armour.insert(ShipClass::Destroyer, 2);
+        let mut game = Game::with_rules(rules, 3);
+        for p in [Player::Player1, Player::Player2] {
+            for y in 0..5 {
+                game.add_ship(p, Location::new(0, y * 2), ShipDir::Horz)
+                    .unwrap();
+            }
+        }
+        game.change_to_playing().unwrap();
+
+        // the destroyer's first cell is destroyed and its second only dented
+        let shots = [(0, 0), (9, 9), (0, 0), (9, 8), (1, 0)];
+        for (i, (x, y)) in shots.into_iter().enumerate() {
+            let player = [Player::Player1, Player::Player2][i % 2];
+            game.guess_position(player, Location::new(x, y)).unwrap();
+        }
+        game.repair(Player::Player2, Location::new(0, 0)).unwrap();
+        game.guess_position(Player::Player1, Location::new(9, 9))
+            .unwrap();
+        game.repair(Player::Player2, Location::new(1, 0)).unwrap();
+        let ship = &game.get_grid(Player::Player2).ships[0];
+        assert_eq!(ship.hit_points(Location::new(0, 0)), Some(2));
+        assert_eq!(ship.hit_points(Location::new(1, 0)), Some(2));
+
+        // player 1 sees the cell they destroyed as destroyed, and the dent as a dent
+        let view = game.view(Player::Player1);
+        assert_eq!(view.hits, vec![Location::new(0, 0)]);
+        assert_eq!(view.dented, vec![Location::new(1, 0)]);
+    }
+
+    #[test]
+    fn test_replay_invalid_action() {
+        let saved = SavedGame {
+            seed: 0,
+            rules: Rules::standard(10),
+            actions: vec![Action::Guess {
+                player: Player::Player1,
+                loc: Location::new(0, 0),
+            }],
+        };
+        assert!(matches!(
+            Game::from_saved(&saved),
+            Err(ReplayError::InvalidAction(0))
+        ));
+    }
+}
@@ -0,0 +1,437 @@
+//! Works out how likely each cell of a partially observed grid is to contain a ship, by looking at
+//! every way the remaining fleet could be placed that agrees with what has been seen so far.
+
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{
+    location::{rect, row_label, Location, Topology},
+    ship::placements,
+    view::PlayerView,
+};
+
+/// How many layouts the exact mode will look at before giving up and leaving it to sampling.
+const EXACT_LIMIT: u64 = 200_000;
+/// How many layouts are sampled when there are too many to look at all of them.
+const SAMPLES: usize = 2000;
+/// Random layouts that don't agree with the view are thrown away, so give up eventually.
+const MAX_ATTEMPTS: usize = 2_000_000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heatmap {
+    pub size: i32,
+    pub depths: i32,
+    /// The chance of each cell containing a ship, indexed by `(layer * size + y) * size + x`.
+    pub probs: Vec<f64>,
+    /// How many consistent layouts the probabilities were worked out from.
+    pub layouts: u64,
+}
+
+impl Heatmap {
+    fn new(view: &PlayerView) -> Self {
+        Self {
+            size: view.size,
+            depths: view.depths,
+            probs: vec![0.0; cell_count(view)],
+            layouts: 0,
+        }
+    }
+
+    pub fn get(&self, coord: Location) -> f64 {
+        self.probs[cell_index(self.size, coord)]
+    }
+
+    /// Every cell on every layer.
+    fn cells(&self) -> impl Iterator<Item = Location> + '_ {
+        (0..=self.depths).flat_map(move |layer| {
+            (0..self.size)
+                .flat_map(move |y| (0..self.size).map(move |x| Location::at_depth(x, y, layer)))
+        })
+    }
+
+    /// The unknown cell most likely to contain a ship.
+    pub fn best(&self, view: &PlayerView) -> Option<Location> {
+        let mut best: Option<(Location, f64)> = None;
+        for l in self.cells() {
+            if is_known(view, l) {
+                continue;
+            }
+            if best.is_none_or(|(_, p)| self.get(l) > p) {
+                best = Some((l, self.get(l)));
+            }
+        }
+        best.map(|(l, _)| l)
+    }
+
+    /// Turns the layout counts into probabilities and fills in the cells already known.
+    fn finish(mut self, view: &PlayerView) -> Self {
+        if self.layouts > 0 {
+            for p in &mut self.probs {
+                *p /= self.layouts as f64;
+            }
+        }
+        for &c in view
+            .hits
+            .iter()
+            .chain(&view.dented)
+            .chain(view.sunk.iter().flat_map(|(_, s)| s))
+        {
+            self.probs[cell_index(self.size, c)] = 1.0;
+        }
+        self
+    }
+
+    pub fn get_display<'h>(&'h self, view: &'h PlayerView) -> HeatmapDisplay<'h> {
+        HeatmapDisplay {
+            heatmap: self,
+            view,
+        }
+    }
+}
+
+fn cell_count(view: &PlayerView) -> usize {
+    (view.size * view.size * (view.depths + 1)) as usize
+}
+
+fn cell_index(size: i32, coord: Location) -> usize {
+    ((coord.layer * size + coord.y) * size + coord.x) as usize
+}
+
+fn is_known(view: &PlayerView, coord: Location) -> bool {
+    view.hits.contains(&coord) || view.misses.contains(&coord) || view.is_sunk_at(coord)
+}
+
+/// Every place each remaining ship could go, ignoring the other remaining ships, as lists of
+/// cell indices.
+fn candidates(view: &PlayerView) -> Vec<Vec<Vec<usize>>> {
+    let mut remaining = view.remaining.clone();
+    remaining.sort_by_key(|c| std::cmp::Reverse(c.size()));
+    remaining
+        .iter()
+        .map(|class| {
+            class
+                .layers(view.depths)
+                .flat_map(|layer| {
+                    let ships = placements(view.size, view.topology, class.size());
+                    ships.into_iter().map(move |s| s.on_layer(layer))
+                })
+                .filter(|ship| {
+                    ship.get_coords()
+                        .iter()
+                        .all(|&c| !view.misses.contains(&c) && !view.is_sunk_at(c))
+                })
+                .map(|ship| {
+                    let cells = ship.get_coords().iter();
+                    cells.map(|&c| cell_index(view.size, c)).collect()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Keeps track of which cells the ships placed so far cover.
+struct Layout {
+    occupied: Vec<bool>,
+    hits: Vec<usize>,
+}
+
+impl Layout {
+    fn new(view: &PlayerView) -> Self {
+        Self {
+            occupied: vec![false; cell_count(view)],
+            hits: view
+                .hits
+                .iter()
+                .chain(&view.dented)
+                .map(|&h| cell_index(view.size, h))
+                .collect(),
+        }
+    }
+
+    fn fits(&self, ship: &[usize]) -> bool {
+        ship.iter().all(|&i| !self.occupied[i])
+    }
+
+    fn set(&mut self, ship: &[usize], value: bool) {
+        for &i in ship {
+            self.occupied[i] = value;
+        }
+    }
+
+    fn covers_hits(&self) -> bool {
+        self.hits.iter().all(|&i| self.occupied[i])
+    }
+
+    fn add_to(&self, heatmap: &mut Heatmap) {
+        for (p, &o) in heatmap.probs.iter_mut().zip(&self.occupied) {
+            if o {
+                *p += 1.0;
+            }
+        }
+        heatmap.layouts += 1;
+    }
+}
+
+/// Works out the exact probabilities by going through every consistent layout. Returns `None`
+/// if there are too many layouts for that to be quick.
+pub fn exact_heatmap(view: &PlayerView) -> Option<Heatmap> {
+    fn search(
+        candidates: &[Vec<Vec<usize>>],
+        layout: &mut Layout,
+        heatmap: &mut Heatmap,
+        visited: &mut u64,
+    ) -> bool {
+        *visited += 1;
+        if *visited > EXACT_LIMIT {
+            return false;
+        }
+        let Some((ships, rest)) = candidates.split_first() else {
+            if layout.covers_hits() {
+                layout.add_to(heatmap);
+            }
+            return true;
+        };
+        for ship in ships {
+            if !layout.fits(ship) {
+                continue;
+            }
+            layout.set(ship, true);
+            let finished = search(rest, layout, heatmap, visited);
+            layout.set(ship, false);
+            if !finished {
+                return false;
+            }
+        }
+        true
+    }
+
+    let candidates = candidates(view);
+    let mut heatmap = Heatmap::new(view);
+    let mut visited = 0;
+    if !search(
+        &candidates,
+        &mut Layout::new(view),
+        &mut heatmap,
+        &mut visited,
+    ) {
+        return None;
+    }
+    Some(heatmap.finish(view))
+}
+
+/// Estimates the probabilities from `samples` random layouts that agree with the view.
+pub fn sampled_heatmap(view: &PlayerView, samples: usize, rng: &mut impl Rng) -> Heatmap {
+    let candidates = candidates(view);
+    let mut heatmap = Heatmap::new(view);
+    let mut layout = Layout::new(view);
+
+    for _ in 0..MAX_ATTEMPTS {
+        if heatmap.layouts as usize >= samples {
+            break;
+        }
+        layout.occupied.fill(false);
+        // throwing away the whole layout when ships overlap keeps every layout equally likely
+        let mut complete = true;
+        for ships in &candidates {
+            match ships.choose(rng) {
+                Some(ship) if layout.fits(ship) => layout.set(ship, true),
+                _ => {
+                    complete = false;
+                    break;
+                }
+            }
+        }
+        if complete && layout.covers_hits() {
+            layout.add_to(&mut heatmap);
+        }
+    }
+    heatmap.finish(view)
+}
+
+/// Uses the exact probabilities when there are few enough layouts, and sampling otherwise.
+pub fn probability_heatmap(view: &PlayerView, rng: &mut impl Rng) -> Heatmap {
+    exact_heatmap(view).unwrap_or_else(|| sampled_heatmap(view, SAMPLES, rng))
+}
+
+pub struct HeatmapDisplay<'h> {
+    heatmap: &'h Heatmap,
+    view: &'h PlayerView,
+}
+
+impl<'h> std::fmt::Display for HeatmapDisplay<'h> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let best = self.heatmap.best(self.view);
+        for layer in 0..=self.heatmap.depths {
+            match layer {
+                _ if self.heatmap.depths == 0 => {}
+                0 => writeln!(f, "Surface")?,
+                _ => writeln!(f, "Depth {layer}")?,
+            }
+            let label_width = row_label(self.heatmap.size - 1).len();
+            write!(f, "{:1$}", "", label_width + 1)?;
+            for x in 1..self.heatmap.size + 1 {
+                write!(f, "{x} ")?;
+            }
+            writeln!(f)?;
+
+            for y in 0..self.heatmap.size {
+                write!(f, "{:label_width$} ", row_label(y))?;
+                if self.view.topology == Topology::Hex {
+                    write!(f, "{:1$}", "", y as usize)?;
+                }
+                for cur in rect(Location::at_depth(0, y, layer), self.heatmap.size, 1) {
+                    if self.view.is_sunk_at(cur) {
+                        write!(f, "# ")?;
+                    } else if self.view.hits.contains(&cur) {
+                        write!(f, "x ")?;
+                    } else if self.view.misses.contains(&cur) {
+                        write!(f, "- ")?;
+                    } else if Some(cur) == best {
+                        write!(f, "* ")?;
+                    } else if self.view.dented.contains(&cur) {
+                        write!(f, "+ ")?;
+                    } else {
+                        let bucket = (self.heatmap.get(cur) * 10.0).floor().min(9.0);
+                        write!(f, "{} ", bucket as u32)?;
+                    }
+                }
+                writeln!(f)?;
+            }
+        }
+        writeln!(
+            f,
+            "Numbers are the chance of a ship in tenths, * is the best guess ({} layouts)",
+            self.heatmap.layouts
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ship::ShipClass;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn view(remaining: Vec<ShipClass>) -> PlayerView {
+        PlayerView {
+            size: 4,
+            topology: Topology::Flat,
+            depths: 0,
+            hits: vec![],
+            dented: vec![],
+            misses: vec![],
+            sunk: vec![],
+            decoys: vec![],
+            remaining,
+        }
+    }
+
+    #[test]
+    fn test_exact_single_ship() {
+        // a destroyer on a 4x4 board can go in 24 places, and the corners are only in 2 of them
+        let heatmap = exact_heatmap(&view(vec![ShipClass::Destroyer])).unwrap();
+        assert_eq!(heatmap.layouts, 24);
+        assert_eq!(heatmap.get(Location::new(0, 0)), 2.0 / 24.0);
+        assert_eq!(heatmap.get(Location::new(1, 1)), 4.0 / 24.0);
+    }
+
+    #[test]
+    fn test_exact_with_hits_and_misses() {
+        let mut v = view(vec![ShipClass::Destroyer]);
+        v.hits.push(Location::new(0, 0));
+        v.misses.push(Location::new(1, 0));
+        let heatmap = exact_heatmap(&v).unwrap();
+        assert_eq!(heatmap.layouts, 1);
+        assert_eq!(heatmap.get(Location::new(0, 0)), 1.0);
+        assert_eq!(heatmap.get(Location::new(0, 1)), 1.0);
+        assert_eq!(heatmap.get(Location::new(1, 1)), 0.0);
+        assert_eq!(heatmap.best(&v), Some(Location::new(0, 1)));
+    }
+
+    #[test]
+    fn test_dented_cells_are_best() {
+        let mut v = view(vec![ShipClass::Destroyer]);
+        v.dented.push(Location::new(2, 2));
+        let heatmap = exact_heatmap(&v).unwrap();
+        assert_eq!(heatmap.layouts, 4);
+        assert_eq!(heatmap.best(&v), Some(Location::new(2, 2)));
+    }
+
+    #[test]
+    fn test_sunk_cells_are_blocked() {
+        let mut v = view(vec![ShipClass::Destroyer]);
+        v.sunk.push((
+            Some(ShipClass::Cruiser),
+            vec![
+                Location::new(0, 1),
+                Location::new(1, 1),
+                Location::new(2, 1),
+            ],
+        ));
+        let heatmap = exact_heatmap(&v).unwrap();
+        assert_eq!(heatmap.get(Location::new(1, 1)), 1.0);
+        assert_eq!(
+            heatmap.get(Location::new(0, 0)),
+            1.0 / heatmap.layouts as f64
+        );
+    }
+
+    #[test]
+    fn test_sampled_close_to_exact() {
+        let v = view(vec![ShipClass::Destroyer, ShipClass::Submarine]);
+        let exact = exact_heatmap(&v).unwrap();
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let sampled = sampled_heatmap(&v, 5000, &mut rng);
+        assert_eq!(sampled.layouts, 5000);
+        for (e, s) in exact.probs.iter().zip(&sampled.probs) {
+            assert!((e - s).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn test_exact_torus() {
+        // on a torus every cell is as likely as every other
+        let mut v = view(vec![ShipClass::Destroyer]);
+        v.topology = Topology::Torus;
+        let heatmap = exact_heatmap(&v).unwrap();
+        assert_eq!(heatmap.layouts, 32);
+        assert_eq!(heatmap.get(Location::new(0, 0)), 4.0 / 32.0);
+        assert_eq!(heatmap.get(Location::new(1, 1)), 4.0 / 32.0);
+    }
+
+    #[test]
+    fn test_exact_hex() {
+        let mut v = view(vec![ShipClass::Destroyer]);
+        v.topology = Topology::Hex;
+        let heatmap = exact_heatmap(&v).unwrap();
+        assert_eq!(heatmap.layouts, 33);
+        // the two sharp corners of the rhombus only have two neighbours
+        assert_eq!(heatmap.get(Location::new(0, 0)), 2.0 / 33.0);
+        assert_eq!(heatmap.get(Location::new(3, 0)), 3.0 / 33.0);
+    }
+
+    #[test]
+    fn test_exact_layers() {
+        // the submarine can only be below the surface and the destroyer only on it
+        let mut v = view(vec![ShipClass::Destroyer, ShipClass::Submarine]);
+        v.depths = 1;
+        let heatmap = exact_heatmap(&v).unwrap();
+        assert_eq!(heatmap.layouts, 24 * 16);
+        assert_eq!(heatmap.get(Location::new(0, 0)), 2.0 / 24.0);
+        assert_eq!(heatmap.get(Location::at_depth(0, 0, 1)), 2.0 / 16.0);
+        assert_eq!(heatmap.get(Location::at_depth(1, 0, 1)), 3.0 / 16.0);
+    }
+
+    #[test]
+    fn test_exact_gives_up_on_big_boards() {
+        let mut v = view(vec![
+            ShipClass::Destroyer,
+            ShipClass::Submarine,
+            ShipClass::Cruiser,
+            ShipClass::Battleship,
+            ShipClass::Carrier,
+        ]);
+        v.size = 10;
+        assert_eq!(exact_heatmap(&v), None);
+    }
+}
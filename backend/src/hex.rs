@@ -0,0 +1,158 @@
+//! Coordinates for hex boards.
+//!
+//! Hexes use axial coordinates: `q` goes along a row and `r` goes down the rows, with every row
+//! shifted half a hex to the right of the one above it. A board of `size` is the rhombus where
+//! both are in `0..size`. That means a hex fits in a `Location` with `x = q` and `y = r`, so grids
+//! store hexes the same way as squares and only need to know which neighbours are which.
+
+use crate::location::Location;
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Deserialize, serde::Serialize,
+)]
+pub struct HexCoord {
+    pub q: i32,
+    pub r: i32,
+}
+
+/// The six directions out of a hex, for pointy-topped hexes.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Deserialize, serde::Serialize,
+)]
+pub enum HexDir {
+    East,
+    West,
+    NorthEast,
+    NorthWest,
+    SouthEast,
+    SouthWest,
+}
+
+impl HexDir {
+    pub const ALL: [HexDir; 6] = [
+        HexDir::East,
+        HexDir::West,
+        HexDir::NorthEast,
+        HexDir::NorthWest,
+        HexDir::SouthEast,
+        HexDir::SouthWest,
+    ];
+
+    /// One direction along each of the three axes, so every straight line is only counted once.
+    pub const AXES: [HexDir; 3] = [HexDir::East, HexDir::SouthEast, HexDir::SouthWest];
+
+    /// How far one step in this direction moves `q` and `r`.
+    pub fn offset(&self) -> (i32, i32) {
+        match self {
+            HexDir::East => (1, 0),
+            HexDir::West => (-1, 0),
+            HexDir::NorthEast => (1, -1),
+            HexDir::NorthWest => (0, -1),
+            HexDir::SouthEast => (0, 1),
+            HexDir::SouthWest => (-1, 1),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HexDir::East => "E",
+            HexDir::West => "W",
+            HexDir::NorthEast => "NE",
+            HexDir::NorthWest => "NW",
+            HexDir::SouthEast => "SE",
+            HexDir::SouthWest => "SW",
+        }
+    }
+
+    pub fn from_input(input: &str) -> Option<HexDir> {
+        HexDir::ALL
+            .into_iter()
+            .find(|d| d.name().eq_ignore_ascii_case(input))
+    }
+}
+
+impl HexCoord {
+    pub fn new(q: i32, r: i32) -> Self {
+        Self { q, r }
+    }
+
+    /// The third cube coordinate, so that `q + r + s == 0`.
+    pub fn s(&self) -> i32 {
+        -self.q - self.r
+    }
+
+    pub fn step(&self, dir: HexDir) -> HexCoord {
+        let (dq, dr) = dir.offset();
+        HexCoord::new(self.q + dq, self.r + dr)
+    }
+
+    /// `len` hexes in a straight line starting here.
+    pub fn line(&self, dir: HexDir, len: i32) -> Vec<HexCoord> {
+        let (dq, dr) = dir.offset();
+        (0..len)
+            .map(|i| HexCoord::new(self.q + dq * i, self.r + dr * i))
+            .collect()
+    }
+
+    pub fn neighbours(&self) -> [HexCoord; 6] {
+        HexDir::ALL.map(|d| self.step(d))
+    }
+
+    /// How many steps it takes to get from one hex to the other.
+    pub fn distance(&self, other: HexCoord) -> u32 {
+        let dq = (self.q - other.q).unsigned_abs();
+        let dr = (self.r - other.r).unsigned_abs();
+        let ds = (self.s() - other.s()).unsigned_abs();
+        dq.max(dr).max(ds)
+    }
+
+    pub fn is_neighbour(&self, other: HexCoord) -> bool {
+        self.distance(other) == 1
+    }
+}
+
+impl From<Location> for HexCoord {
+    fn from(loc: Location) -> Self {
+        HexCoord::new(loc.x, loc.y)
+    }
+}
+
+impl From<HexCoord> for Location {
+    fn from(hex: HexCoord) -> Self {
+        Location::new(hex.q, hex.r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_distance() {
+        let origin = HexCoord::new(0, 0);
+        for n in origin.neighbours() {
+            assert_eq!(origin.distance(n), 1);
+            assert!(origin.is_neighbour(n));
+        }
+        assert_eq!(origin.distance(HexCoord::new(2, -1)), 2);
+        assert_eq!(origin.distance(HexCoord::new(2, 2)), 4);
+        assert_eq!(HexCoord::new(3, -1).s(), -2);
+        // (1, 1) looks diagonal, but on hexes it's two steps away
+        assert!(!origin.is_neighbour(HexCoord::new(1, 1)));
+    }
+
+    #[test]
+    fn test_hex_line() {
+        assert_eq!(
+            HexCoord::new(2, 0).line(HexDir::SouthWest, 3),
+            vec![
+                HexCoord::new(2, 0),
+                HexCoord::new(1, 1),
+                HexCoord::new(0, 2)
+            ]
+        );
+        assert_eq!(HexDir::from_input("ne"), Some(HexDir::NorthEast));
+        assert_eq!(HexDir::from_input("N"), None);
+        assert_eq!(Location::from(HexCoord::new(1, 2)), Location::new(1, 2));
+    }
+}
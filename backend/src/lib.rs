@@ -1,40 +1,110 @@
 pub mod game;
+pub mod heatmap;
+pub mod hex;
 pub mod location;
+pub mod notation;
+pub mod practice;
+pub mod render;
+pub mod room;
+pub mod session;
 pub mod ship;
+pub mod solitaire;
+pub mod stats;
+pub mod svg;
+pub mod view;
 
 use std::{
     borrow::Cow,
+    collections::HashMap,
     io::{BufRead, Write},
     net::SocketAddr,
-    sync::Arc,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use anyhow::bail;
 use axum::{
     extract::{
         ws::{CloseFrame, Message, WebSocket},
-        State, WebSocketUpgrade,
+        Path, Query, State, WebSocketUpgrade,
     },
-    response::IntoResponse,
-    routing::get,
-    Error, Json, Router,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
 };
 use futures::{
     sink::SinkExt,
-    stream::{select, SplitSink, SplitStream, StreamExt},
+    stream::{SplitSink, StreamExt},
 };
-use game::{Game, Player, StateOrOtherError};
-use location::Location;
-use serde::{Deserialize, Serialize};
-use ship::{Grid, Ship, ShipDir};
-use tokio::sync::Mutex;
+use game::{
+    Disclosure, Game, Phase, Player, PlayerRules, Rules, ShotOutcome, StateOrOtherError,
+    SHIPS_ORDER,
+};
+use heatmap::probability_heatmap;
+use hex::{HexCoord, HexDir};
+use location::{Dir, Location, Topology};
+use notation::Record;
+use practice::{BestScore, BestScores, Practice};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use render::Renderer;
+use room::{Joined, RoomError, RoomInfo, Rooms};
+use serde::{Deserialize, Deserializer, Serialize};
+use session::{new_token, Sessions};
+use ship::ShipClass;
+use solitaire::{Difficulty, Mark, Puzzle};
+use stats::GameStats;
+use tokio::{
+    sync::{mpsc, watch},
+    time::Instant,
+};
+use view::PlayerView;
+
+use crate::game::{ChangeToPlayingError, CheckWinError, GuessError, RepairError, SetFleetError};
+
+/// What every connection to the server shares. Games don't go in here, each one belongs to the
+/// task running it.
+struct ServerState {
+    config: ServerConfig,
+    /// Where new players go to be paired up with an opponent.
+    queue: mpsc::UnboundedSender<WebSocket>,
+    rooms: Rooms<Connection>,
+    /// The games being played, and how recent ones ended, by the session tokens of their players.
+    sessions: Sessions<Session>,
+    /// What's been shown to spectators of the games played in rooms, by room code. Finished
+    /// games stay in here until their room expires, so that latecomers can still see the end.
+    watching: Mutex<HashMap<String, watch::Receiver<Vec<Snapshot>>>>,
+}
+
+/// Settings for the web server.
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    /// How long a player whose connection dropped has to come back before they forfeit.
+    pub grace_period: Duration,
+}
 
-use crate::game::{ChangeToPlayingError, CheckWinError, GuessError};
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            grace_period: Duration::from_secs(60),
+        }
+    }
+}
 
-struct WaitingState {
-    websockets: Mutex<Vec<WebSocket>>,
+/// A player who has joined but isn't in a game yet.
+struct Connection {
+    socket: WebSocket,
+    /// The token they were given to get back into their game.
+    token: String,
 }
 
+/// How often rooms that have expired are cleared out.
+const ROOM_SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+/// How long players can still come back to see how their game ended.
+const FINISHED_SESSION_TTL: Duration = Duration::from_secs(10 * 60);
+
 fn read_line_parse<T>(f: impl Fn(String) -> Result<T, String>) -> T {
     let stdin = std::io::stdin();
     for line in stdin.lock().lines() {
@@ -54,44 +124,154 @@ fn read_line_parse<T>(f: impl Fn(String) -> Result<T, String>) -> T {
 }
 
 fn create_ships_for_player(player: Player, game: &mut Game) {
-    for _ in 0..5 {
+    while game.get_grid(player).ships.len() < game.pieces(player).len() {
         println!("{}", game.get_grid(player).get_display(true));
+        let class = game.pieces(player)[game.get_grid(player).ships.len()];
+        if game.placing_decoy(player) {
+            println!("Place a decoy that looks like a {class}.");
+        }
         loop {
-            print!("Enter the starting X coordinate: ");
+            print!("Enter the cell the ship starts at, like B7: ");
             std::io::stdout().flush().unwrap();
-            let x = read_line_parse(|s| {
-                s.parse::<i32>()
-                    .map_err(|_| "Please enter a valid integer: ".to_string())
-            }) - 1;
+            let start = read_line_parse(|s| {
+                s.parse::<Location>()
+                    .map_err(|e| format!("{e}, please try again: "))
+            });
+
+            // submarines dive, so ask how deep when there's a choice
+            let layers = class.layers(game.rules().depths);
+            let layer = if layers.start() == layers.end() {
+                *layers.start()
+            } else {
+                print!(
+                    "Your {class} dives, how deep should it go (1-{}): ",
+                    layers.end()
+                );
+                std::io::stdout().flush().unwrap();
+                read_line_parse(|s| {
+                    s.parse::<i32>()
+                        .map_err(|_| "Please enter a valid integer: ".to_string())
+                })
+            };
+
+            let res = if game.rules().topology == Topology::Hex {
+                print!("Which way should it point (E, W, NE, NW, SE or SW): ");
+                std::io::stdout().flush().unwrap();
+                let dir = read_line_parse(|s| {
+                    HexDir::from_input(s.trim())
+                        .ok_or_else(|| "Please enter one of E, W, NE, NW, SE or SW: ".to_string())
+                });
+                game.add_hex_ship(player, HexCoord::new(start.x, start.y), layer, dir)
+            } else {
+                print!("Which way should it go from there, up ('U'), down ('D'), left ('L') or right ('R'): ");
+                std::io::stdout().flush().unwrap();
+                let dir = read_line_parse(|s| {
+                    Dir::from_screen(s.trim())
+                        .ok_or_else(|| "Please enter one of U, D, L or R: ".to_string())
+                });
+                game.add_ship(player, start.with_layer(layer), dir)
+            };
 
-            print!("Enter the starting Y coordinate: ");
+            match res {
+                Ok(_) => break,
+                Err(e) => println!("The ship could not be placed because: {e}"),
+            }
+        }
+    }
+}
+
+/// Reads the coordinates of a shot. If a view is given the player can type "hint" to see how
+/// likely each cell is to have a ship in it.
+fn read_guess(view: Option<&PlayerView>) -> Location {
+    print!("Enter a cell, like B7: ");
+    std::io::stdout().flush().unwrap();
+    let loc = read_line_parse(|s| match (s.trim(), view) {
+        ("hint", Some(view)) => {
+            // hints aren't part of the game, so they mustn't use the game's RNG
+            let heatmap = probability_heatmap(view, &mut rand::thread_rng());
+            Err(format!(
+                "{}Enter a cell, like B7: ",
+                heatmap.get_display(view)
+            ))
+        }
+        (s, _) => s
+            .parse::<Location>()
+            .map_err(|e| format!("{e}, please try again: ")),
+    });
+
+    let layer = match view {
+        Some(view) if view.depths > 0 => {
+            print!(
+                "Enter the depth (0 for the surface, 1-{} for a depth charge): ",
+                view.depths
+            );
             std::io::stdout().flush().unwrap();
-            let y = read_line_parse(|s| {
+            read_line_parse(|s| {
                 s.parse::<i32>()
                     .map_err(|_| "Please enter a valid integer: ".to_string())
-            }) - 1;
+            })
+        }
+        _ => 0,
+    };
 
-            print!("Do you want it to be horizontal ('H') or vertical ('V'): ");
-            std::io::stdout().flush().unwrap();
-            let dir = read_line_parse(|s| match &*s {
-                "H" | "h" => Ok(ShipDir::Horz),
-                "V" | "v" => Ok(ShipDir::Vert),
-                _ => Err("Please enter either H or V: ".to_string()),
-            });
+    loc.with_layer(layer)
+}
 
-            let res = game.add_ship(player, Location { x, y }, dir);
+fn print_outcome(outcome: ShotOutcome) {
+    match outcome {
+        ShotOutcome::Sunk(class) => println!("You have sunk an enemy {class}!"),
+        ShotOutcome::SunkUnnamed => println!("You have sunk an enemy ship!"),
+        ShotOutcome::Decoy => println!("You have sunk an enemy decoy, it wasn't a real ship!"),
+        ShotOutcome::Hit => println!("You have hit an enemy ship!"),
+        ShotOutcome::Armoured => println!("You have hit an enemy ship, but its armour held!"),
+        ShotOutcome::Miss => println!("You are not epic!"),
+    }
+}
 
-            match res {
-                Ok(_) => break,
-                Err(e) => println!("The ship could not be placed because: {e}"),
+fn practice(bests_path: PathBuf) {
+    let mut practice = Practice::new(10);
+    println!("Practice seed: {}", practice.seed());
+    println!("Sink the whole fleet in as few shots as you can!");
+
+    while !practice.is_cleared() {
+        println!("================");
+        println!("{}", practice.get_grid().get_display(false));
+        println!("Shots so far: {}", practice.shots());
+        match practice.fire(read_guess(None)) {
+            Ok(outcome) => print_outcome(outcome),
+            Err(e) => println!("That shot doesn't count: {e}"),
+        }
+    }
+
+    println!("{}", practice.get_grid().get_display(false));
+    println!("You cleared the board in {} shots!", practice.shots());
+
+    let score = BestScore {
+        shots: practice.shots(),
+        seed: practice.seed(),
+    };
+    let saved = BestScores::load(&bests_path).and_then(|mut bests| {
+        let place = bests.record(score);
+        bests.save(&bests_path)?;
+        Ok((place, bests))
+    });
+    match saved {
+        Ok((place, bests)) => {
+            if let Some(place) = place {
+                println!("That's number {place} in your personal bests!");
+            }
+            println!("Personal bests:");
+            for (i, s) in bests.scores.iter().enumerate() {
+                println!("{:>2}. {} shots (seed {})", i + 1, s.shots, s.seed);
             }
         }
+        Err(e) => println!("Could not update your personal bests: {e}"),
     }
 }
 
-fn turn(game: &mut Game, player: Player) -> Option<Player> {
+fn turn(game: &mut Game, player: Player, renderer: Renderer) -> Option<Player> {
     println!("================");
-    println!("{}", game.get_grid(player.other()).get_display(false));
+    print!("{}", renderer.turn(game, player));
 
     println!(
         "Player {} please type your guess:",
@@ -100,30 +280,85 @@ fn turn(game: &mut Game, player: Player) -> Option<Player> {
             Player::Player2 => 2,
         }
     );
+    if game.rules().player(player).shots_per_turn > 1 {
+        println!("({} shots left this turn)", game.shots_left().unwrap());
+    }
 
-    print!("Enter the X coordinate: ");
-    std::io::stdout().flush().unwrap();
-    let x = read_line_parse(|s| {
-        s.parse::<i32>()
-            .map_err(|_| "Please enter a valid integer: ".to_string())
-    }) - 1;
+    if offer_repair(game, player) {
+        return None;
+    }
 
-    print!("Enter the Y coordinate: ");
-    std::io::stdout().flush().unwrap();
-    let y = read_line_parse(|s| {
-        s.parse::<i32>()
-            .map_err(|_| "Please enter a valid integer: ".to_string())
-    }) - 1;
+    println!("(type 'hint' instead of a coordinate to see where the enemy ships probably are)");
+    let loc = read_guess(Some(&game.view(player)));
+    let outcome = game.guess_position(player, loc).unwrap();
+    println!("Firing at {loc:#}...");
+    print_outcome(outcome);
+
+    print!("{}", renderer.turn(game, player));
 
-    let success = game.guess_position(player, Location { x, y }).unwrap();
-    match success {
-        true => println!("You have hit an enemy ship!"),
-        false => println!("You are not epic!"),
+    game.check_if_win().unwrap()
+}
+
+/// Lets the player spend their turn repairing a ship if they can. Returns whether they did.
+fn offer_repair(game: &mut Game, player: Player) -> bool {
+    let damaged = game.get_grid(player).ships.iter().any(|s| {
+        !s.is_sunk()
+            && s.get_coords()
+                .iter()
+                .any(|&c| s.hit_points(c) < Some(s.armour()))
+    });
+    // repairs take the whole turn, so they can't be made after firing
+    let turn_started = game.shots_left() != Some(game.rules().player(player).shots_per_turn.max(1));
+    if game.repairs_left(player) == 0 || turn_started || !damaged {
+        return false;
     }
 
-    println!("{}", game.get_grid(player.other()).get_display(false));
+    println!("{}", game.get_grid(player).get_display(true));
+    loop {
+        print!(
+            "You have {} repairs left, type 'repair' to fix a damaged cell instead of firing: ",
+            game.repairs_left(player)
+        );
+        std::io::stdout().flush().unwrap();
+        let answer = read_line_parse(|s| Ok(s.trim().to_lowercase()));
+        if answer != "repair" {
+            return false;
+        }
+        let loc = read_guess(None);
+        match game.repair(player, loc) {
+            Ok(()) => {
+                if !game.rules().hide_repairs {
+                    println!("Player {} has repaired one of their ships!", player.num());
+                }
+                return true;
+            }
+            Err(e) => println!("That cell could not be repaired because: {e}"),
+        }
+    }
+}
 
-    game.check_if_win().unwrap()
+/// Applies one `--p1-<name> <value>` handicap flag, returning false if it doesn't make sense.
+fn set_handicap(rules: &mut PlayerRules, name: &str, value: &str) -> bool {
+    match name {
+        "size" => match value.parse() {
+            Ok(size) if (1..=26).contains(&size) => rules.size = size,
+            _ => return false,
+        },
+        "shots" => match value.parse() {
+            Ok(shots) if shots >= 1 => rules.shots_per_turn = shots,
+            _ => return false,
+        },
+        "fleet" => match value.split(',').map(ShipClass::from_name).collect() {
+            Some(fleet) => rules.fleet = fleet,
+            None => return false,
+        },
+        "decoys" => match value.split(',').map(ShipClass::from_name).collect() {
+            Some(decoys) => rules.decoys = decoys,
+            None => return false,
+        },
+        _ => return false,
+    }
+    true
 }
 
 pub fn main_old() {
@@ -135,21 +370,188 @@ pub fn main_old() {
     //griddy.add_ship(new_ship2).unwrap();
     //griddy.add_ship(new_ship3).unwrap();
     //print!("{}", griddy.get_display(true));
-    let mut game = Game::new(10);
-    println!("Player 1 please place your ships on the grid:");
-    create_ships_for_player(Player::Player1, &mut game);
-    println!("Player 2 please place your ships on the grid:");
-    create_ships_for_player(Player::Player2, &mut game);
+    let mut save_path = None;
+    let mut load_path = None;
+    let mut svg_path = None;
+    let mut practice_mode = false;
+    let mut renderer = Renderer::detect();
+    let mut bests_path = BestScores::default_path();
+    let mut rules = Rules::standard(10);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let ok = match (&*arg, args.len()) {
+            ("--save", 1..) => {
+                save_path = args.next();
+                true
+            }
+            ("--load", 1..) => {
+                load_path = args.next();
+                true
+            }
+            ("--svg", 1..) => {
+                svg_path = args.next();
+                true
+            }
+            ("--practice", _) => {
+                practice_mode = true;
+                true
+            }
+            ("--bests", 1..) => {
+                bests_path = args.next().unwrap().into();
+                true
+            }
+            ("--no-colour", _) => {
+                renderer = Renderer::plain();
+                true
+            }
+            ("--torus", _) => {
+                rules.topology = Topology::Torus;
+                true
+            }
+            ("--hex", _) => {
+                rules.topology = Topology::Hex;
+                true
+            }
+            ("--armour", 1..) => {
+                let armour: Option<Vec<(ShipClass, u32)>> = args
+                    .next()
+                    .unwrap()
+                    .split(',')
+                    .map(|entry| {
+                        let (class, hits) = entry.split_once(':')?;
+                        Some((ShipClass::from_name(class)?, hits.parse().ok()?))
+                    })
+                    .collect();
+                match armour {
+                    Some(armour) => {
+                        rules.armour.extend(armour);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            ("--repairs", 1..) => match args.next().unwrap().parse() {
+                Ok(repairs) => {
+                    rules.repairs = repairs;
+                    true
+                }
+                _ => false,
+            },
+            ("--hide-repairs", _) => {
+                rules.hide_repairs = true;
+                true
+            }
+            ("--reveal-decoys", _) => {
+                rules.reveal_decoys = true;
+                true
+            }
+            ("--disclosure", 1..) => match Disclosure::from_name(&args.next().unwrap()) {
+                Some(disclosure) => {
+                    rules.disclosure = disclosure;
+                    true
+                }
+                None => false,
+            },
+            ("--depths", 1..) => match args.next().unwrap().parse() {
+                Ok(depths) if depths >= 0 => {
+                    rules.depths = depths;
+                    true
+                }
+                _ => false,
+            },
+            (flag, 1..) if flag.starts_with("--p1-") || flag.starts_with("--p2-") => {
+                let player = if flag.starts_with("--p1-") { 0 } else { 1 };
+                set_handicap(
+                    &mut rules.players[player],
+                    &flag[5..],
+                    &args.next().unwrap(),
+                )
+            }
+            _ => false,
+        };
+        if !ok {
+            println!(
+                "Usage: cmd [--load <notation file>] [--save <notation file>] [--svg <image file>] [variants] [handicaps]"
+            );
+            println!("       cmd --practice [--bests <personal bests file>]");
+            println!("  --no-colour        draw the boards without colours");
+            println!("Variants:");
+            println!("  --torus            the edges of the board wrap around");
+            println!("  --hex              play on hexes instead of squares");
+            println!("  --depths <n>       add layers under the surface for submarines");
+            println!("  --armour <armour>  hits per cell for armoured ships, e.g. Carrier:2");
+            println!("  --repairs <n>      turns each player can spend repairing a ship");
+            println!("  --hide-repairs     don't tell the other player about repairs");
+            println!("  --reveal-decoys    say so when a decoy is sunk");
+            println!("  --disclosure <d>   what shots reveal: hits, sinks or classes");
+            println!("Handicaps, for --p1-... or --p2-...:");
+            println!("  --p1-size <n>      the size of player 1's grid");
+            println!("  --p1-shots <n>     how many shots player 1 fires each turn");
+            println!("  --p1-fleet <ships> player 1's ships, e.g. Destroyer,Cruiser,Carrier");
+            println!("  --p1-decoys <ships> decoys player 1 places after their fleet");
+            return;
+        }
+    }
+    // the flags are fine one at a time, but together they could ask for more than fits
+    if let Err(e) = rules.check() {
+        println!("Those rules can't be played because {e}");
+        return;
+    }
 
-    game.change_to_playing().unwrap();
+    if practice_mode {
+        practice(bests_path);
+        return;
+    }
 
-    let winner = loop {
-        if let Some(p) = turn(&mut game, Player::Player1) {
-            break p;
+    let (mut game, players) = match load_path {
+        Some(path) => {
+            let loaded = std::fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|text| {
+                    let record = text.parse::<Record>()?;
+                    Ok((record.to_game()?, record.header.players))
+                });
+            match loaded {
+                Ok(loaded) => loaded,
+                Err(e) => {
+                    println!("Could not load {path}: {e}");
+                    return;
+                }
+            }
         }
-        if let Some(p) = turn(&mut game, Player::Player2) {
-            break p;
+        None => (
+            Game::with_rules(rules, rand::random()),
+            [String::from("Player 1"), String::from("Player 2")],
+        ),
+    };
+    println!("Game seed: {}", game.seed());
+
+    if game.phase() == Phase::CreateShips {
+        println!("Player 1 please place your ships on the grid:");
+        create_ships_for_player(Player::Player1, &mut game);
+        println!("Player 2 please place your ships on the grid:");
+        create_ships_for_player(Player::Player2, &mut game);
+
+        game.change_to_playing().unwrap();
+    }
+
+    let winner = match game.winner() {
+        // the loaded game is already over so just show how it went
+        Some(winner) => {
+            print!("{}", Record::from_game(&game, players.clone()));
+            for p in [Player::Player1, Player::Player2] {
+                println!("Player {}'s grid:", p.num());
+                println!("{}", game.get_grid(p).get_display(true));
+            }
+            winner
         }
+        None => loop {
+            // players with extra shots keep going until they have used them all
+            let player = game.get_turn().unwrap();
+            if let Some(p) = turn(&mut game, player, renderer) {
+                break p;
+            }
+        },
     };
 
     println!(
@@ -159,23 +561,164 @@ pub fn main_old() {
             Player::Player2 => 2,
         }
     );
+    print!("{}", GameStats::from_game(&game));
+
+    if let Some(path) = save_path {
+        let record = Record::from_game(&game, players);
+        match std::fs::write(&path, record.to_string()) {
+            Ok(_) => println!("The game has been saved to {path}"),
+            Err(e) => println!("Could not save the game to {path}: {e}"),
+        }
+    }
+
+    if let Some(path) = svg_path {
+        match std::fs::write(&path, svg::game(&game)) {
+            Ok(_) => println!("A picture of the game has been saved to {path}"),
+            Err(e) => println!("Could not save the picture to {path}: {e}"),
+        }
+    }
 }
 
-pub async fn main() {
-    let app = Router::new()
-        .route("/", get(root))
-        .route("/ws", get(ws_handler))
-        .with_state(Arc::new(WaitingState {
-            websockets: Mutex::new(vec![]),
-        }));
+pub fn main_solitaire() {
+    let mut difficulty = Difficulty::Medium;
+    let mut seed = rand::random();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &*arg {
+            "easy" => difficulty = Difficulty::Easy,
+            "medium" => difficulty = Difficulty::Medium,
+            "hard" => difficulty = Difficulty::Hard,
+            "--seed" => match args.next().and_then(|s| s.parse().ok()) {
+                Some(s) => seed = s,
+                None => {
+                    println!("Please give a valid seed after --seed");
+                    return;
+                }
+            },
+            _ => {
+                println!("Usage: solitaire [easy|medium|hard] [--seed <seed>]");
+                return;
+            }
+        }
+    }
+
+    println!("Puzzle seed: {seed}");
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let (puzzle, solution) = Puzzle::generate(10, &SHIPS_ORDER, difficulty, &mut rng)
+        .expect("the standard fleet always fits on a 10x10 board");
+    let mut marks = vec![Mark::Unknown; (puzzle.size * puzzle.size) as usize];
+
+    println!(
+        "Find the hidden fleet! The numbers show how many ship cells are in each row and column,"
+    );
+    println!("and ships never touch each other, not even diagonally.");
+    loop {
+        println!("{}", puzzle.get_display(&marks));
+        print!("Enter 'ship B7', 'water B7', 'clear B7', 'check', 'reveal' or 'quit': ");
+        std::io::stdout().flush().unwrap();
+        let (cmd, loc) = read_line_parse(|s| {
+            let words: Vec<&str> = s.split_whitespace().collect();
+            let parse = |w: &str| {
+                w.parse::<Location>()
+                    .ok()
+                    .filter(|l| l.x >= 0 && l.y >= 0 && l.x < puzzle.size && l.y < puzzle.size)
+                    .ok_or("Please enter a cell on the board, like B7: ".to_string())
+            };
+            match words[..] {
+                [cmd @ ("check" | "reveal" | "quit")] => Ok((cmd.to_string(), None)),
+                [cmd @ ("ship" | "water" | "clear"), loc] => {
+                    Ok((cmd.to_string(), Some(parse(loc)?)))
+                }
+                _ => Err("Please enter a valid command: ".to_string()),
+            }
+        });
 
+        let mark = match (&*cmd, loc) {
+            ("ship", Some(l)) => Some((l, Mark::Ship)),
+            ("water", Some(l)) => Some((l, Mark::Water)),
+            ("clear", Some(l)) => Some((l, Mark::Unknown)),
+            _ => None,
+        };
+        if let Some((l, mark)) = mark {
+            marks[(l.y * puzzle.size + l.x) as usize] = mark;
+            if !puzzle.check(&marks, &solution) {
+                continue;
+            }
+        }
+
+        match &*cmd {
+            "check" if !puzzle.check(&marks, &solution) => {
+                println!("Not quite, keep going!");
+                continue;
+            }
+            "reveal" => {
+                println!("{}", solution.get_display(true));
+                return;
+            }
+            "quit" => return,
+            _ => {}
+        }
+        println!("{}", puzzle.get_display(&marks));
+        println!("Congratulations, you found the whole fleet!");
+        return;
+    }
+}
+
+pub async fn main() {
     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
     axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+        .serve(app().into_make_service())
         .await
         .unwrap();
 }
 
+/// All of the server's routes. This starts the task that pairs players up, so it has to be called
+/// from inside the runtime.
+pub fn app() -> Router {
+    app_with_config(ServerConfig::default())
+}
+
+pub fn app_with_config(config: ServerConfig) -> Router {
+    let (queue, waiting) = mpsc::unbounded_channel();
+    let state = Arc::new(ServerState {
+        config,
+        queue,
+        rooms: Rooms::default(),
+        sessions: Sessions::default(),
+        watching: Mutex::new(HashMap::new()),
+    });
+    tokio::spawn(pair_players(state.clone(), waiting));
+
+    let sweeping = state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ROOM_SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            sweeping.rooms.sweep();
+            sweeping.sessions.retain(|session| match session {
+                Session::Playing(..) => true,
+                Session::Over(_, ended) => ended.elapsed() < FINISHED_SESSION_TTL,
+            });
+            // games still going are kept even if their room has gone, since they can run long
+            let mut watching = sweeping.watching.lock().unwrap();
+            watching.retain(|code, history| {
+                history.has_changed().is_ok() || sweeping.rooms.info(code).is_ok()
+            });
+        }
+    });
+
+    Router::new()
+        .route("/", get(root))
+        .route("/ws", get(ws_handler))
+        .route("/ws/practice", get(practice_ws_handler))
+        .route("/ws/:code", get(room_ws_handler))
+        .route("/ws/resume/:token", get(resume_ws_handler))
+        .route("/ws/watch/:code", get(watch_ws_handler))
+        .route("/rooms", post(create_room))
+        .route("/rooms/:code", get(room_info))
+        .with_state(state)
+}
+
 #[derive(Serialize)]
 struct RootResponse {
     swag: i32,
@@ -192,40 +735,371 @@ async fn root() -> Json<RootResponse> {
 
 async fn ws_handler(
     ws: WebSocketUpgrade,
-    State(state): State<Arc<WaitingState>>,
+    State(state): State<Arc<ServerState>>,
 ) -> impl IntoResponse {
     ws.on_upgrade(|socket| handle_socket(socket, state))
 }
 
-async fn handle_socket(mut socket: WebSocket, state: Arc<WaitingState>) {
-    let mut websockets = state.websockets.lock().await;
+async fn handle_socket(socket: WebSocket, state: Arc<ServerState>) {
+    // the queue only goes away if the server is shutting down
+    let _ = state.queue.send(socket);
+}
 
-    if socket
-        .send(Message::Text(
-            serde_json::to_string(&GameState::Waiting).unwrap(),
-        ))
-        .await
+/// Takes players off the queue two at a time and starts a game for each pair in a task of its
+/// own, so however long a game takes the next players are paired straight away.
+async fn pair_players(state: Arc<ServerState>, mut queue: mpsc::UnboundedReceiver<WebSocket>) {
+    // the player waiting for an opponent and the seed of the game they're waiting for
+    let mut waiting: Option<(Connection, u64)> = None;
+    loop {
+        let socket = match &mut waiting {
+            Some((first, _)) => tokio::select! {
+                biased;
+                _ = closed(&mut first.socket) => {
+                    // whoever comes next shouldn't be paired with a player who has left
+                    waiting = None;
+                    continue;
+                }
+                socket = queue.recv() => socket,
+            },
+            None => queue.recv().await,
+        };
+        let Some(mut socket) = socket else { return };
+
+        let seed = match &waiting {
+            Some((_, seed)) => *seed,
+            None => rand::random(),
+        };
+        let token = new_token();
+        let msg = GameState::Waiting {
+            seed,
+            token: token.clone(),
+        };
+        if (socket
+            .send(Message::Text(serde_json::to_string(&msg).unwrap()))
+            .await)
+            .is_err()
+        {
+            continue;
+        }
+
+        let joined = Connection { socket, token };
+        match waiting.take() {
+            Some((first, seed)) => {
+                let game = Game::with_seed(10, seed);
+                tokio::spawn(play_pair(state.clone(), [first, joined], game, None));
+            }
+            None => waiting = Some((joined, seed)),
+        }
+    }
+}
+
+/// Waits for the player to leave, ignoring anything they send in the meantime.
+async fn closed(socket: &mut WebSocket) {
+    loop {
+        match socket.recv().await {
+            Some(Ok(Message::Close(_)) | Err(_)) | None => return,
+            Some(Ok(_)) => {}
+        }
+    }
+}
+
+async fn close(mut socket: WebSocket, code: u16, reason: &'static str) {
+    let _ = socket
+        .send(Message::Close(Some(CloseFrame {
+            code,
+            reason: Cow::from(reason),
+        })))
+        .await;
+}
+
+/// Runs a game between two players who have just been paired, for as long as it lasts. Games in a
+/// room can be watched using the room's code.
+async fn play_pair(
+    state: Arc<ServerState>,
+    players: [Connection; 2],
+    game: Game,
+    code: Option<String>,
+) {
+    let (history, watching) = watch::channel(vec![]);
+    let audience = Audience { history };
+    if let Some(code) = &code {
+        state
+            .watching
+            .lock()
+            .unwrap()
+            .insert(code.clone(), watching);
+    }
+
+    let (events, mut inbox) = mpsc::unbounded_channel();
+    let mut seats = Seats {
+        sinks: [None, None],
+        connections: [0; 2],
+        deadlines: [None; 2],
+        grace_period: state.config.grace_period,
+        events: events.clone(),
+        last_sent: [None, None],
+    };
+    let mut tokens = vec![];
+    for (p, joined) in [Player::Player1, Player::Player2].into_iter().zip(players) {
+        state
+            .sessions
+            .insert(joined.token.clone(), Session::Playing(p, events.clone()));
+        tokens.push(joined.token);
+        seats.seat(p, joined.socket);
+    }
+
+    let result = do_game(&mut seats, &mut inbox, &audience, game).await;
+    for (p, token) in [Player::Player1, Player::Player2].into_iter().zip(tokens) {
+        match &seats.last_sent[p as usize] {
+            // anyone who comes back too late is shown how the game ended
+            Some(last) if result.is_ok() => {
+                let session = Session::Over(last.clone(), Instant::now());
+                state.sessions.insert(token, session);
+            }
+            _ => state.sessions.remove(&token),
+        }
+    }
+    let (close_code, reason) = match result {
+        Ok(()) => (axum::extract::ws::close_code::NORMAL, "Game Finished"),
+        Err(_) => (axum::extract::ws::close_code::ERROR, "Game Error"),
+    };
+    // the room's spectators are left with the end of the game, which the sweep clears out
+    drop(audience);
+    seats.close(close_code, reason).await;
+}
+
+async fn resume_ws_handler(
+    ws: WebSocketUpgrade,
+    Path(token): Path<String>,
+    State(state): State<Arc<ServerState>>,
+) -> Response {
+    let session = state.sessions.get(&token);
+    ws.on_upgrade(move |mut socket| async move {
+        match session {
+            Some(Session::Playing(player, game)) => {
+                // the game can end between looking it up and getting here
+                if let Err(mpsc::error::SendError(Event::Rejoined(_, socket))) =
+                    game.send(Event::Rejoined(player, Box::new(socket)))
+                {
+                    close(
+                        *socket,
+                        axum::extract::ws::close_code::NORMAL,
+                        "Game Finished",
+                    )
+                    .await;
+                }
+            }
+            Some(Session::Over(last, _)) => {
+                let _ = socket.send(Message::Text(last)).await;
+                close(
+                    socket,
+                    axum::extract::ws::close_code::NORMAL,
+                    "Game Finished",
+                )
+                .await;
+            }
+            // the connection is made just to say so, since browsers can't see why one was refused
+            None => {
+                close(
+                    socket,
+                    axum::extract::ws::close_code::POLICY,
+                    "Unknown Session",
+                )
+                .await;
+            }
+        }
+    })
+}
+
+/// The longest spectators can ask to be kept behind the game.
+const MAX_SPECTATOR_DELAY: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Deserialize, Debug)]
+struct WatchParams {
+    /// How many seconds behind the game spectators are kept, so that they can't tip players off.
+    #[serde(default)]
+    delay: u64,
+}
+
+async fn watch_ws_handler(
+    ws: WebSocketUpgrade,
+    Path(code): Path<String>,
+    Query(params): Query<WatchParams>,
+    State(state): State<Arc<ServerState>>,
+) -> Response {
+    let code = code.to_uppercase();
+    let running = state.watching.lock().unwrap().contains_key(&code);
+    if !running {
+        if let Err(e) = state.rooms.info(&code) {
+            return (StatusCode::NOT_FOUND, e.to_string()).into_response();
+        }
+    }
+    let delay = Duration::from_secs(params.delay).min(MAX_SPECTATOR_DELAY);
+    ws.on_upgrade(move |socket| watch_game(socket, code, delay, state))
+}
+
+/// Whether what came in on a connection means the other end has gone.
+fn is_gone(m: &Option<Result<Message, axum::Error>>) -> bool {
+    matches!(m, None | Some(Err(_)) | Some(Ok(Message::Close(_))))
+}
+
+/// Shows a spectator the game in a room as it goes, `delay` behind. Nothing they send is listened
+/// to except for them leaving.
+async fn watch_game(socket: WebSocket, code: String, delay: Duration, state: Arc<ServerState>) {
+    let (mut sink, mut stream) = socket.split();
+
+    // spectators can turn up before the players do
+    let mut history = loop {
+        if let Some(history) = state.watching.lock().unwrap().get(&code) {
+            break history.clone();
+        }
+        if state.rooms.info(&code).is_err() {
+            let _ = sink
+                .send(Message::Close(Some(CloseFrame {
+                    code: axum::extract::ws::close_code::NORMAL,
+                    reason: Cow::from("Room Closed"),
+                })))
+                .await;
+            return;
+        }
+        tokio::select! {
+            m = stream.next() => {
+                if is_gone(&m) {
+                    return;
+                }
+            }
+            _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+        }
+    };
+
+    let mut shown = 0;
+    let mut playing = true;
+    loop {
+        // the most recent state that's old enough to show, and when the next one will be
+        let (latest, next) = {
+            let history = history.borrow_and_update();
+            let now = Instant::now();
+            let due = history.iter().take_while(|s| s.at + delay <= now).count();
+            let latest = (due > shown).then(|| history[due - 1].state.clone());
+            shown = due;
+            (latest, history.get(due).map(|s| s.at + delay))
+        };
+        if let Some(latest) = latest {
+            if sink.send(Message::Text(latest.to_string())).await.is_err() {
+                return;
+            }
+        }
+        if !playing && next.is_none() {
+            break;
+        }
+
+        tokio::select! {
+            m = stream.next() => {
+                if is_gone(&m) {
+                    return;
+                }
+            }
+            changed = history.changed(), if playing => playing = changed.is_ok(),
+            _ = tokio::time::sleep_until(next.unwrap_or_else(Instant::now)), if next.is_some() => {}
+        }
+    }
+
+    let _ = sink
+        .send(Message::Close(Some(CloseFrame {
+            code: axum::extract::ws::close_code::NORMAL,
+            reason: Cow::from("Game Finished"),
+        })))
+        .await;
+}
+
+/// Makes a private room with the rules in the body, or the standard ones if there isn't one.
+async fn create_room(
+    State(state): State<Arc<ServerState>>,
+    rules: Option<Json<Rules>>,
+) -> Result<Json<RoomInfo>, (StatusCode, String)> {
+    let rules = rules.map_or_else(|| Rules::standard(10), |Json(rules)| rules);
+    match state.rooms.create(rules) {
+        Ok(info) => Ok(Json(info)),
+        Err(e) => Err((StatusCode::BAD_REQUEST, e.to_string())),
+    }
+}
+
+async fn room_info(
+    Path(code): Path<String>,
+    State(state): State<Arc<ServerState>>,
+) -> Result<Json<RoomInfo>, (StatusCode, String)> {
+    match state.rooms.info(&code) {
+        Ok(info) => Ok(Json(info)),
+        Err(e) => Err((StatusCode::NOT_FOUND, e.to_string())),
+    }
+}
+
+async fn room_ws_handler(
+    ws: WebSocketUpgrade,
+    Path(code): Path<String>,
+    State(state): State<Arc<ServerState>>,
+) -> Response {
+    // turn away codes that were never any good before upgrading, it's too late to say why after
+    if let Err(e) = state.rooms.info(&code) {
+        return (StatusCode::NOT_FOUND, e.to_string()).into_response();
+    }
+    ws.on_upgrade(|socket| handle_room_socket(socket, code, state))
+}
+
+async fn handle_room_socket(mut socket: WebSocket, code: String, state: Arc<ServerState>) {
+    let (info, joined) = match state.rooms.join(&code) {
+        Ok(joined) => joined,
+        Err(e) => {
+            let reason = match e {
+                RoomError::Started => "Room Full",
+                _ => "Room Not Found",
+            };
+            close(socket, axum::extract::ws::close_code::POLICY, reason).await;
+            return;
+        }
+    };
+
+    let token = new_token();
+    let msg = GameState::Waiting {
+        seed: info.seed,
+        token: token.clone(),
+    };
+    if (socket
+        .send(Message::Text(serde_json::to_string(&msg).unwrap()))
+        .await)
         .is_err()
     {
         return;
     }
 
-    websockets.push(socket);
-
-    if websockets.len() == 2 {
-        let (s, r): (Vec<_>, Vec<_>) = websockets.drain(..).map(|w| w.split()).unzip();
-        let mut s: [_; 2] = s.try_into().unwrap();
-        let r: [_; 2] = r.try_into().unwrap();
-
-        let game = Game::new(10);
-        if do_game(&mut s, r, game).await.is_err() {
-            for mut w in s {
-                let _ = w
-                    .send(Message::Close(Some(CloseFrame {
-                        code: axum::extract::ws::close_code::ERROR,
-                        reason: Cow::from("Game Error"),
-                    })))
+    match joined {
+        Joined::Second(waiting) => {
+            if let Err(joined) = waiting.send(Connection { socket, token }) {
+                let code = axum::extract::ws::close_code::AWAY;
+                close(joined.socket, code, "Opponent Left").await;
+            }
+        }
+        Joined::First(opponent) => {
+            // the game runs here once the opponent turns up, unless this player leaves first
+            let opponent = tokio::select! {
+                biased;
+                _ = closed(&mut socket) => return,
+                opponent = opponent => opponent,
+            };
+            match opponent {
+                Ok(opponent) => {
+                    let game = Game::with_rules(info.rules, info.seed);
+                    let players = [Connection { socket, token }, opponent];
+                    play_pair(state, players, game, Some(info.code)).await;
+                }
+                Err(_) => {
+                    close(
+                        socket,
+                        axum::extract::ws::close_code::NORMAL,
+                        "Room Expired",
+                    )
                     .await;
+                }
             }
         }
     }
@@ -234,27 +1108,64 @@ async fn handle_socket(mut socket: WebSocket, state: Arc<WaitingState>) {
 #[derive(Deserialize, Debug)]
 struct AddShipCommand {
     loc: Location,
-    dir: ShipDir,
+    /// The way the ship goes as it looks on the screen, so `Up` is towards row A. The old `Horz`
+    /// and `Vert` still work, as right and down.
+    #[serde(deserialize_with = "screen_dir")]
+    dir: Dir,
+}
+
+fn screen_dir<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Dir, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    let dir = match &*name {
+        "Horz" => Some(Dir::Right),
+        "Vert" => Some(Dir::Up),
+        "Up" | "Down" | "Left" | "Right" => Dir::from_screen(&name),
+        _ => None,
+    };
+    dir.ok_or_else(|| serde::de::Error::custom(format!("\"{name}\" isn't a direction")))
+}
+/// A player's whole fleet at once, in the order the ships are placed.
+#[derive(Deserialize, Debug)]
+struct PlaceFleetCommand {
+    ships: Vec<AddShipCommand>,
 }
 #[derive(Deserialize, Debug)]
 struct GuessPosCommand {
     loc: Location,
 }
+#[derive(Deserialize, Debug)]
+struct RepairCommand {
+    loc: Location,
+}
 
 #[derive(Deserialize, Debug)]
 #[serde(tag = "type")]
 enum Command {
     AddShip(AddShipCommand),
+    PlaceFleet(PlaceFleetCommand),
     GuessPos(GuessPosCommand),
+    Repair(RepairCommand),
 }
 
 #[derive(Serialize, Debug)]
 #[serde(tag = "type")]
 enum GameState {
-    Waiting,
+    Waiting {
+        #[serde(serialize_with = "game::serialize_seed")]
+        seed: u64,
+        /// Lets the player back into their game if they lose their connection once it's started.
+        token: String,
+    },
     Adding {
         ships: Vec<Vec<Location>>,
         size: i32,
+        /// How many layers the board has under the surface, each drawn next to the surface.
+        depths: i32,
+        #[serde(serialize_with = "game::serialize_seed")]
+        seed: u64,
+        /// Why the player's last `PlaceFleet` was turned down, if it was.
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        errors: Vec<ShipRejection>,
     },
     Guessing {
         you: Player,
@@ -262,16 +1173,81 @@ enum GameState {
 
         your_correct_guesses: Vec<Location>,
         your_incorrect_guesses: Vec<Location>,
+        /// Armoured cells you've hit that can take more hits.
+        your_dented: Vec<Location>,
 
         opps_correct_guesses: Vec<Location>,
         opps_incorrect_guesses: Vec<Location>,
+        opps_dented: Vec<Location>,
         your_ships: Vec<Vec<Location>>,
 
         size: i32,
+        /// The opponent's grid can be a different size when playing with handicaps.
+        opps_size: i32,
+        depths: i32,
+        repairs_left: u32,
     },
     Won {
         who: Player,
+        stats: GameStats,
+        /// Whether the game ended because the loser didn't come back in time after leaving.
+        forfeit: bool,
+    },
+    /// What spectators see while the game is going on. Nothing that's hidden from the players is
+    /// in here.
+    Spectating {
+        /// Whose turn it is, once the ships have been placed.
+        turn: Option<Player>,
+        ships_placed: [usize; 2],
+        /// Each player's board as their opponent sees it.
+        boards: [PlayerView; 2],
     },
+    /// What spectators see once the game is over, which is everything.
+    Finished {
+        winner: Player,
+        forfeit: bool,
+        boards: [PlayerView; 2],
+        ships: [Vec<Vec<Location>>; 2],
+        stats: GameStats,
+    },
+    Practicing {
+        correct_guesses: Vec<Location>,
+        incorrect_guesses: Vec<Location>,
+        shots: u32,
+        size: i32,
+        #[serde(serialize_with = "game::serialize_seed")]
+        seed: u64,
+    },
+    Cleared {
+        shots: u32,
+        #[serde(serialize_with = "game::serialize_seed")]
+        seed: u64,
+    },
+}
+
+#[derive(Serialize, Debug)]
+struct ShipRejection {
+    /// Which ship in the layout it was, or none if the layout as a whole was wrong.
+    ship: Option<usize>,
+    reason: String,
+}
+
+impl ShipRejection {
+    fn from_error(error: SetFleetError) -> Vec<Self> {
+        match error {
+            SetFleetError::Ships(errors) => errors
+                .into_iter()
+                .map(|(i, e)| ShipRejection {
+                    ship: Some(i),
+                    reason: e.to_string(),
+                })
+                .collect(),
+            e @ SetFleetError::WrongCount { .. } => vec![ShipRejection {
+                ship: None,
+                reason: e.to_string(),
+            }],
+        }
+    }
 }
 
 // this macro reduces boring code duplication, needs to be a macro because one of the arguments is the type of command to match
@@ -290,140 +1266,369 @@ macro_rules! message_to_cmd {
     };
 }
 
-async fn do_game(
-    senders: &mut [SplitSink<WebSocket, axum::extract::ws::Message>; 2],
-    [r1, r2]: [SplitStream<WebSocket>; 2],
-    mut game: Game,
-) -> anyhow::Result<()> {
-    let stream1 = r1.map(|m| (Player::Player1, m));
-    let stream2 = r2.map(|m| (Player::Player2, m));
-    let mut combined_stream = select(stream1, stream2);
+/// Something that happened to one of the players in a game.
+enum Event {
+    /// A message from the player's connection with the given number.
+    Message(Player, u32, Message),
+    /// The player's connection with the given number has dropped.
+    Left(Player, u32),
+    /// The player has come back with their session token.
+    Rejoined(Player, Box<WebSocket>),
+}
 
-    macro_rules! send_adding {
-        () => {
-            for p in [Player::Player1, Player::Player2] {
-                let grid = game.get_grid(p);
-                let mut ships = vec![];
-                for s in grid.ships.iter() {
-                    let mut ship = vec![];
-                    for c in s.get_coords() {
-                        ship.push(*c);
-                    }
-                    ships.push(ship);
+/// How to get back into a game, or how it ended for a player who comes back after it's over.
+#[derive(Clone)]
+enum Session {
+    /// Which player it's for, and where to tell the game they're back.
+    Playing(Player, mpsc::UnboundedSender<Event>),
+    /// The last thing the player was sent, and when the game ended.
+    Over(String, Instant),
+}
+
+/// Both players' connections to a game, which can drop and come back while it's going on.
+struct Seats {
+    sinks: [Option<SplitSink<WebSocket, Message>>; 2],
+    /// How many connections each player has had, so that anything still coming in on an old one
+    /// can be ignored.
+    connections: [u32; 2],
+    /// When each player who has left forfeits, unless they come back first.
+    deadlines: [Option<Instant>; 2],
+    grace_period: Duration,
+    events: mpsc::UnboundedSender<Event>,
+    /// The last state each player was sent, whether they were there for it or not.
+    last_sent: [Option<String>; 2],
+}
+
+impl Seats {
+    fn seat(&mut self, p: Player, socket: WebSocket) {
+        let (sink, mut stream) = socket.split();
+        let i = p as usize;
+        self.connections[i] += 1;
+        self.sinks[i] = Some(sink);
+        self.deadlines[i] = None;
+
+        let connection = self.connections[i];
+        let events = self.events.clone();
+        tokio::spawn(async move {
+            while let Some(Ok(m)) = stream.next().await {
+                if matches!(m, Message::Close(_)) {
+                    break;
+                }
+                if events.send(Event::Message(p, connection, m)).is_err() {
+                    return;
                 }
-                let msg = GameState::Adding { ships, size: 10 };
-                let msg_str = serde_json::to_string(&msg);
-                let msg_ws = Message::Text(msg_str.unwrap());
-                senders[p as usize].send(msg_ws).await?;
             }
-        };
+            let _ = events.send(Event::Left(p, connection));
+        });
     }
 
-    // ADDING THE SHIPS
-    send_adding!();
-    while let Some((p, m)) = combined_stream.next().await {
-        println!("Received message");
-        let m = m?;
-        let cmd = match message_to_cmd!(Command::AddShip, m) {
-            Some(value) => value,
-            None => continue,
-        };
-        println!("{:?}", cmd);
-        match game.add_ship(p, cmd.loc, cmd.dir) {
-            // "e @ p" means "if the variable matches the pattern p, give me the result, call it e"
-            Err(e @ StateOrOtherError::WrongState) => {
-                // bail comes from anyhow and means "return Err(e) from this function after converting it to an anyhow error"
-                bail!(e);
+    fn is_current(&self, p: Player, connection: u32) -> bool {
+        self.connections[p as usize] == connection
+    }
+
+    fn leave(&mut self, p: Player) {
+        self.sinks[p as usize] = None;
+        self.deadlines[p as usize].get_or_insert(Instant::now() + self.grace_period);
+    }
+
+    /// The player who has been gone longest and when they forfeit, if anyone has left.
+    fn next_forfeit(&self) -> Option<(Player, Instant)> {
+        [Player::Player1, Player::Player2]
+            .into_iter()
+            .filter_map(|p| Some((p, self.deadlines[p as usize]?)))
+            .min_by_key(|&(_, deadline)| deadline)
+    }
+
+    /// Sends the player `state` if they're there. If they aren't, they get the state the game is
+    /// in when they come back instead.
+    async fn send(&mut self, p: Player, state: &GameState) {
+        let text = serde_json::to_string(state).unwrap();
+        self.last_sent[p as usize] = Some(text.clone());
+        if let Some(sink) = &mut self.sinks[p as usize] {
+            if sink.send(Message::Text(text)).await.is_err() {
+                self.leave(p);
             }
-            _ => {}
         }
+    }
 
-        send_adding!();
+    async fn close(&mut self, code: u16, reason: &'static str) {
+        for sink in self.sinks.iter_mut().flatten() {
+            let _ = sink
+                .send(Message::Close(Some(CloseFrame {
+                    code,
+                    reason: Cow::from(reason),
+                })))
+                .await;
+        }
+    }
+}
 
-        match game.change_to_playing() {
-            Err(e @ ChangeToPlayingError::WrongState) => {
-                bail!(e);
-            }
-            Err(ChangeToPlayingError::NotEnoughShips) => continue,
-            Ok(_) => break,
+/// Everything spectators have been shown, which they get with however much delay they asked for.
+struct Audience {
+    history: watch::Sender<Vec<Snapshot>>,
+}
+
+#[derive(Clone, Debug)]
+struct Snapshot {
+    at: Instant,
+    state: Arc<String>,
+}
+
+impl Audience {
+    fn show(&self, state: &GameState) {
+        let snapshot = Snapshot {
+            at: Instant::now(),
+            state: Arc::new(serde_json::to_string(state).unwrap()),
+        };
+        self.history.send_modify(|history| history.push(snapshot));
+    }
+}
+
+fn spectating_state(game: &Game) -> GameState {
+    let both = [Player::Player1, Player::Player2];
+    GameState::Spectating {
+        turn: game.get_turn().ok(),
+        ships_placed: both.map(|p| game.get_grid(p).ships.len()),
+        boards: both.map(|p| game.view(p.other())),
+    }
+}
+
+fn finished_state(game: &Game, winner: Player, forfeit: bool) -> GameState {
+    let both = [Player::Player1, Player::Player2];
+    GameState::Finished {
+        winner,
+        forfeit,
+        boards: both.map(|p| game.view(p.other())),
+        ships: both.map(|p| {
+            let ships = game.get_grid(p).ships.iter();
+            ships.map(|s| s.get_coords().to_vec()).collect()
+        }),
+        stats: GameStats::from_game(game),
+    }
+}
+
+fn adding_state(game: &Game, p: Player, errors: Vec<ShipRejection>) -> GameState {
+    let grid = game.get_grid(p);
+    let mut ships = vec![];
+    for s in grid.ships.iter() {
+        let mut ship = vec![];
+        for c in s.get_coords() {
+            ship.push(*c);
         }
+        ships.push(ship);
+    }
+    GameState::Adding {
+        ships,
+        size: grid.size,
+        depths: grid.depths,
+        seed: game.seed(),
+        errors,
     }
+}
 
-    println!("All ships received, game changed state");
+fn guessing_state(game: &Game, p: Player) -> anyhow::Result<GameState> {
+    let grid = game.get_grid(p);
+    let mut your_ships = vec![];
+    for s in grid.ships.iter() {
+        let mut ship = vec![];
+        for c in s.get_coords() {
+            ship.push(*c);
+        }
+        your_ships.push(ship);
+    }
 
-    macro_rules! send_guessing {
-        () => {
-            for p in [Player::Player1, Player::Player2] {
-                let grid = game.get_grid(p);
-                let mut your_ships = vec![];
-                for s in grid.ships.iter() {
-                    let mut ship = vec![];
-                    for c in s.get_coords() {
-                        ship.push(*c);
-                    }
-                    your_ships.push(ship);
-                }
+    let other_grid = game.get_grid(p.other());
+    // go through the view so that hidden repairs stay hidden
+    let view = game.view(p);
+    let mut your_correct_guesses = view.hits.clone();
+    your_correct_guesses.extend(view.sunk.iter().flat_map(|(_, s)| s));
+    your_correct_guesses.extend(view.decoys.iter().flatten());
+    let your_incorrect_guesses = other_grid.wrong_guesses.clone();
+    let your_dented = view.dented;
 
-                let other_grid = game.get_grid(p.other());
-                let your_correct_guesses = other_grid.get_all_found();
-                let your_incorrect_guesses = other_grid.wrong_guesses.clone();
+    let opps_correct_guesses = grid.get_all_found();
+    let opps_incorrect_guesses = grid.wrong_guesses.clone();
+    let opps_dented = grid.get_all_dented();
 
-                let opps_correct_guesses = grid.get_all_found();
-                let opps_incorrect_guesses = grid.wrong_guesses.clone();
+    let turn = match game.get_turn() {
+        Ok(player) => player,
+        _ => bail!("Cannot return turn due to wrong state"),
+    };
 
-                let turn = match game.get_turn() {
-                    Ok(player) => player,
-                    _ => bail!("Cannot return turn due to wrong state"),
-                };
+    Ok(GameState::Guessing {
+        you: p,
+        your_ships,
+        size: grid.size,
+        opps_size: other_grid.size,
+        depths: grid.depths,
+        repairs_left: game.repairs_left(p),
+        opps_correct_guesses,
+        opps_incorrect_guesses,
+        opps_dented,
+        your_correct_guesses,
+        your_incorrect_guesses,
+        your_dented,
+        turn,
+    })
+}
+
+async fn do_game(
+    seats: &mut Seats,
+    events: &mut mpsc::UnboundedReceiver<Event>,
+    audience: &Audience,
+    mut game: Game,
+) -> anyhow::Result<()> {
+    const BOTH: [Player; 2] = [Player::Player1, Player::Player2];
 
-                let msg = GameState::Guessing {
-                    you: p,
-                    your_ships,
-                    size: 10,
-                    opps_correct_guesses,
-                    opps_incorrect_guesses,
-                    your_correct_guesses,
-                    your_incorrect_guesses,
-                    turn,
+    // ADDING THE SHIPS
+    for p in BOTH {
+        seats.send(p, &adding_state(&game, p, vec![])).await;
+    }
+    audience.show(&spectating_state(&game));
+    loop {
+        let event = match seats.next_forfeit() {
+            Some((p, deadline)) => match tokio::time::timeout_at(deadline, events.recv()).await {
+                Ok(event) => event,
+                Err(_) => {
+                    // they didn't come back in time
+                    let msg = GameState::Won {
+                        who: p.other(),
+                        stats: GameStats::from_game(&game),
+                        forfeit: true,
+                    };
+                    for q in BOTH {
+                        seats.send(q, &msg).await;
+                    }
+                    audience.show(&finished_state(&game, p.other(), true));
+                    return Ok(());
+                }
+            },
+            None => events.recv().await,
+        };
+        // the seats keep a sender, so there are always more events to wait for
+        let Some(event) = event else {
+            bail!("No more events")
+        };
+        let (p, m) = match event {
+            Event::Message(p, connection, m) if seats.is_current(p, connection) => (p, m),
+            Event::Left(p, connection) if seats.is_current(p, connection) => {
+                println!("Player {} left", p.num());
+                seats.leave(p);
+                continue;
+            }
+            Event::Rejoined(p, socket) => {
+                println!("Player {} came back", p.num());
+                seats.seat(p, *socket);
+                let state = match game.phase() {
+                    Phase::CreateShips => adding_state(&game, p, vec![]),
+                    _ => guessing_state(&game, p)?,
                 };
-                let msg_str = serde_json::to_string(&msg);
-                let msg_ws = Message::Text(msg_str.unwrap());
-                senders[p as usize].send(msg_ws).await?;
+                seats.send(p, &state).await;
+                continue;
             }
+            _ => continue,
         };
-    }
 
-    // GUESSING SHIPS
-    send_guessing!();
-    while let Some((p, m)) = combined_stream.next().await {
         println!("Received message");
-        let m = m?;
-        let cmd = match message_to_cmd!(Command::GuessPos, m) {
-            Some(value) => value,
-            None => continue,
+        let Message::Text(s) = m else { continue };
+        let cmd = match serde_json::from_str::<Command>(&s) {
+            Ok(cmd) => cmd,
+            Err(_) => continue,
         };
         println!("{:?}", cmd);
-        match game.guess_position(p, cmd.loc) {
-            Err(e @ GuessError::WrongState) => {
-                bail!(e);
+
+        if game.phase() == Phase::CreateShips {
+            let mut rejections = vec![];
+            match cmd {
+                Command::AddShip(cmd) => {
+                    // "e @ p" means "if the variable matches the pattern p, give me the result, call it e"
+                    if let Err(e @ StateOrOtherError::WrongState) =
+                        game.add_ship(p, cmd.loc, cmd.dir)
+                    {
+                        // bail comes from anyhow and means "return Err(e) from this function after converting it to an anyhow error"
+                        bail!(e);
+                    }
+                }
+                Command::PlaceFleet(cmd) => {
+                    let layout: Vec<_> = cmd.ships.iter().map(|s| (s.loc, s.dir)).collect();
+                    match game.set_fleet(p, &layout) {
+                        Ok(()) => {}
+                        Err(e @ StateOrOtherError::WrongState) => bail!(e),
+                        Err(StateOrOtherError::Other(e)) => {
+                            rejections = ShipRejection::from_error(e);
+                        }
+                    }
+                }
+                _ => continue,
             }
-            Ok(_) => {
-                // "for s in senders" moves out of senders so we need to not do that, ".iter()" is for getting references and ".iter_mut()" is for getting mutable references
-                // for s in senders.iter_mut() {
-                //     s.send(Message::Text(format!(
-                //         "Player {} has guessed {} and {} an enemy ship!",
-                //         p.num(),
-                //         cmd.loc,
-                //         match b {
-                //             false => "missed",
-                //             true => "destroyed",
-                //         }
-                //     )))
-                //     .await?;
-                // }
-                send_guessing!();
+
+            for q in BOTH {
+                let errors = if q == p {
+                    std::mem::take(&mut rejections)
+                } else {
+                    vec![]
+                };
+                seats.send(q, &adding_state(&game, q, errors)).await;
             }
-            _ => {}
+            audience.show(&spectating_state(&game));
+
+            match game.change_to_playing() {
+                Err(e @ ChangeToPlayingError::WrongState) => {
+                    bail!(e);
+                }
+                Err(ChangeToPlayingError::NotEnoughShips) => continue,
+                Ok(_) => {}
+            }
+
+            println!("All ships received, game changed state");
+            // GUESSING SHIPS
+            for q in BOTH {
+                seats.send(q, &guessing_state(&game, q)?).await;
+            }
+            audience.show(&spectating_state(&game));
+            continue;
+        }
+
+        match cmd {
+            Command::Repair(cmd) => {
+                match game.repair(p, cmd.loc) {
+                    Err(e @ RepairError::WrongState) => bail!(e),
+                    Ok(()) => {
+                        for q in BOTH {
+                            seats.send(q, &guessing_state(&game, q)?).await;
+                        }
+                        audience.show(&spectating_state(&game));
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            Command::GuessPos(cmd) => match game.guess_position(p, cmd.loc) {
+                Err(e @ GuessError::WrongState) => {
+                    bail!(e);
+                }
+                Ok(_) => {
+                    // "for s in senders" moves out of senders so we need to not do that, ".iter()" is for getting references and ".iter_mut()" is for getting mutable references
+                    // for s in senders.iter_mut() {
+                    //     s.send(Message::Text(format!(
+                    //         "Player {} has guessed {} and {} an enemy ship!",
+                    //         p.num(),
+                    //         cmd.loc,
+                    //         match b {
+                    //             false => "missed",
+                    //             true => "destroyed",
+                    //         }
+                    //     )))
+                    //     .await?;
+                    // }
+                    for q in BOTH {
+                        seats.send(q, &guessing_state(&game, q)?).await;
+                    }
+                    audience.show(&spectating_state(&game));
+                }
+                _ => {}
+            },
+            _ => continue,
         }
         match game.check_if_win() {
             Err(e @ CheckWinError::WrongState) => {
@@ -431,26 +1636,77 @@ async fn do_game(
             }
             Ok(None) => continue,
             Ok(Some(p)) => {
-                for s in senders.iter_mut() {
-                    s.send(Message::Text(format!(
-                        "Player {} has won the game!",
-                        p.num()
-                    )))
-                    .await?;
+                let msg = GameState::Won {
+                    who: p,
+                    stats: GameStats::from_game(&game),
+                    forfeit: false,
+                };
+                for q in BOTH {
+                    seats.send(q, &msg).await;
                 }
-                break;
+                audience.show(&finished_state(&game, p, false));
+                return Ok(());
             }
         }
     }
+}
+
+async fn practice_ws_handler(ws: WebSocketUpgrade) -> impl IntoResponse {
+    ws.on_upgrade(handle_practice_socket)
+}
+
+async fn handle_practice_socket(mut socket: WebSocket) {
+    let practice = Practice::new(10);
+    let (code, reason) = match do_practice(&mut socket, practice).await {
+        Ok(_) => (axum::extract::ws::close_code::NORMAL, "Game Finished"),
+        Err(_) => (axum::extract::ws::close_code::ERROR, "Game Error"),
+    };
+    let _ = socket
+        .send(Message::Close(Some(CloseFrame {
+            code,
+            reason: Cow::from(reason),
+        })))
+        .await;
+}
 
-    for s in senders.iter_mut() {
-        let _ = s
-            .send(Message::Close(Some(CloseFrame {
-                code: axum::extract::ws::close_code::NORMAL,
-                reason: Cow::from("Game Finished"),
-            })))
-            .await;
+async fn do_practice(socket: &mut WebSocket, mut practice: Practice) -> anyhow::Result<()> {
+    macro_rules! send_practicing {
+        () => {
+            let grid = practice.get_grid();
+            let msg = GameState::Practicing {
+                correct_guesses: grid.get_all_found(),
+                incorrect_guesses: grid.wrong_guesses.clone(),
+                shots: practice.shots(),
+                size: grid.size,
+                seed: practice.seed(),
+            };
+            let msg_str = serde_json::to_string(&msg);
+            socket.send(Message::Text(msg_str.unwrap())).await?;
+        };
     }
 
+    send_practicing!();
+    while !practice.is_cleared() {
+        let m = match socket.recv().await {
+            Some(m) => m?,
+            None => return Ok(()),
+        };
+        let cmd = match message_to_cmd!(Command::GuessPos, m) {
+            Some(value) => value,
+            None => continue,
+        };
+        // shots that don't count are just ignored, like in the two player game
+        if practice.fire(cmd.loc).is_ok() {
+            send_practicing!();
+        }
+    }
+
+    let msg = GameState::Cleared {
+        shots: practice.shots(),
+        seed: practice.seed(),
+    };
+    socket
+        .send(Message::Text(serde_json::to_string(&msg).unwrap()))
+        .await?;
     Ok(())
 }
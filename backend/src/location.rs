@@ -1,5 +1,11 @@
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::{hex::HexCoord, ship::Grid};
+
 fn points_dist(a: i32, b: i32) -> u32 {
-    ((a as i64) - (b as i64)).abs() as u32
+    ((a as i64) - (b as i64)).unsigned_abs() as u32
 }
 
 // Clone - allows .clone()
@@ -16,19 +22,95 @@ fn points_dist(a: i32, b: i32) -> u32 {
 pub struct Location {
     pub x: i32,
     pub y: i32,
+    /// How deep the location is on boards with more than one layer. 0 is the surface, which is
+    /// the only layer ordinary boards have, so it's left out when it's 0.
+    #[serde(default, skip_serializing_if = "is_surface")]
+    pub layer: i32,
+}
+
+fn is_surface(layer: &i32) -> bool {
+    *layer == 0
 }
 
 #[derive(
     Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Deserialize, serde::Serialize,
 )]
+/// A direction on a square board. `Up` is towards bigger `y`, which the grids draw further down
+/// the screen, so use `from_screen` for directions a player points in on a drawn grid.
 pub enum Dir {
+    // ships used to only go horizontally or vertically, so those still read as right and up
+    #[serde(alias = "Vert")]
     Up,
     Down,
     Left,
+    #[serde(alias = "Horz")]
     Right,
 }
 
+/// The shape of the board: whether going off one edge brings you back on the opposite one.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize,
+)]
+pub enum Topology {
+    /// An ordinary board with edges.
+    #[default]
+    Flat,
+    /// Both pairs of opposite edges are joined, so ships can wrap around and cells on opposite
+    /// edges are next to each other.
+    Torus,
+    /// A rhombus of hexes, see the `hex` module for how they map onto locations.
+    Hex,
+}
+
+impl Topology {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Topology::Flat => "flat",
+            Topology::Torus => "torus",
+            Topology::Hex => "hex",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Topology> {
+        [Topology::Flat, Topology::Torus, Topology::Hex]
+            .into_iter()
+            .find(|t| t.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Where `loc` really is on a board of `size`. Flat boards leave it alone, so it might still
+    /// be off the board.
+    pub fn wrap(&self, loc: Location, size: i32) -> Location {
+        match self {
+            Topology::Flat | Topology::Hex => loc,
+            Topology::Torus => {
+                Location::at_depth(loc.x.rem_euclid(size), loc.y.rem_euclid(size), loc.layer)
+            }
+        }
+    }
+
+    /// The distance between two points on one axis, going the short way round if there is one.
+    fn axis_dist(&self, a: i32, b: i32, size: i32) -> u32 {
+        let dist = points_dist(a, b);
+        match self {
+            Topology::Flat | Topology::Hex => dist,
+            Topology::Torus => dist.min((size as u32).saturating_sub(dist)),
+        }
+    }
+}
+
 impl Dir {
+    pub const ALL: [Dir; 4] = [Dir::Up, Dir::Down, Dir::Left, Dir::Right];
+
+    /// How far one step in this direction moves `x` and `y`. Up is towards bigger `y`.
+    pub fn offset(&self) -> (i32, i32) {
+        match self {
+            Dir::Up => (0, 1),
+            Dir::Down => (0, -1),
+            Dir::Left => (-1, 0),
+            Dir::Right => (1, 0),
+        }
+    }
+
     pub fn from_input(input: &str) -> Option<Dir> {
         match &*input.to_uppercase() {
             "DOWN" | "D" => Some(Self::Down),
@@ -38,29 +120,101 @@ impl Dir {
             _ => None,
         }
     }
+
+    /// Reads a direction as it looks on the grid drawn with row A at the top, so up on the screen
+    /// is towards smaller `y`. `H` and `V` mean right and down like horizontal and vertical ships.
+    pub fn from_screen(input: &str) -> Option<Dir> {
+        match &*input.to_uppercase() {
+            "H" => Some(Self::Right),
+            "V" => Some(Self::Up),
+            _ => Self::from_input(input).map(|d| d.flip_vertical()),
+        }
+    }
+
+    /// The letter for this direction as it looks on the drawn grid, the other way to `from_screen`.
+    pub fn screen_name(&self) -> &'static str {
+        match self {
+            Dir::Up => "D",
+            Dir::Down => "U",
+            Dir::Left => "L",
+            Dir::Right => "R",
+        }
+    }
+
+    fn flip_vertical(self) -> Dir {
+        match self {
+            Dir::Up => Dir::Down,
+            Dir::Down => Dir::Up,
+            other => other,
+        }
+    }
 }
 
 impl Location {
     pub fn new(x: i32, y: i32) -> Self {
-        Self { x, y }
+        Self { x, y, layer: 0 }
+    }
+
+    /// A location below the surface, at depth `layer`.
+    pub fn at_depth(x: i32, y: i32, layer: i32) -> Self {
+        Self { x, y, layer }
+    }
+
+    /// The same spot on a different layer.
+    pub fn with_layer(self, layer: i32) -> Self {
+        Self { layer, ..self }
     }
     pub fn move_dir(&mut self, dir: Dir) {
-        match dir {
-            Dir::Up => {
-                self.y += 1;
-            }
-            Dir::Down => {
-                self.y -= 1;
-            }
-            Dir::Left => {
-                self.x -= 1;
-            }
-            Dir::Right => {
-                self.x += 1;
-            }
+        *self = self.step(dir);
+    }
+
+    /// The location one step away in `dir`, on the same layer.
+    pub fn step(self, dir: Dir) -> Location {
+        self.offset_by(dir.offset())
+    }
+
+    fn offset_by(self, (dx, dy): (i32, i32)) -> Location {
+        Location {
+            x: self.x + dx,
+            y: self.y + dy,
+            ..self
         }
     }
 
+    /// `len` locations in a straight line starting here and going in `dir`.
+    pub fn line(self, dir: Dir, len: i32) -> impl Iterator<Item = Location> {
+        let (dx, dy) = dir.offset();
+        (0..len).map(move |i| self.offset_by((dx * i, dy * i)))
+    }
+
+    /// The four locations that share an edge with this one.
+    pub fn orthogonal_neighbours(self) -> impl Iterator<Item = Location> {
+        Dir::ALL.into_iter().map(move |d| self.step(d))
+    }
+
+    /// The four locations that only share a corner with this one.
+    pub fn diagonal_neighbours(self) -> impl Iterator<Item = Location> {
+        [(1, 1), (1, -1), (-1, 1), (-1, -1)]
+            .into_iter()
+            .map(move |o| self.offset_by(o))
+    }
+
+    /// All eight locations touching this one.
+    pub fn neighbours(self) -> impl Iterator<Item = Location> {
+        self.orthogonal_neighbours()
+            .chain(self.diagonal_neighbours())
+    }
+
+    /// How many orthogonal steps it takes to get to `other`.
+    pub fn manhattan_distance(&self, other: Location) -> u32 {
+        points_dist(self.x, other.x).saturating_add(points_dist(self.y, other.y))
+    }
+
+    /// How many steps it takes to get to `other` when diagonal steps are allowed.
+    pub fn chebyshev_distance(&self, other: Location) -> u32 {
+        points_dist(self.x, other.x).max(points_dist(self.y, other.y))
+    }
+
     pub fn get_distance(&self, other: Location) -> f32 {
         let xdist = points_dist(self.x, other.x);
         let ydist = points_dist(self.y, other.y);
@@ -68,15 +222,134 @@ impl Location {
         temp.sqrt()
     }
 
-    #[allow(dead_code)]
+    /// Whether `other` is touching this location, diagonals included. A location counts as
+    /// touching itself.
     pub fn check_neighbour(&self, other: Location) -> bool {
-        !(points_dist(self.x, other.x) > 1 && points_dist(self.y, other.y) > 1)
+        self.chebyshev_distance(other) <= 1
+    }
+
+    /// Like `get_distance`, but measured on a board of `size` with the given topology.
+    pub fn get_distance_on(&self, other: Location, topology: Topology, size: i32) -> f32 {
+        if topology == Topology::Hex {
+            return HexCoord::from(*self).distance(other.into()) as f32;
+        }
+        let xdist = topology.axis_dist(self.x, other.x, size);
+        let ydist = topology.axis_dist(self.y, other.y, size);
+        let temp = (xdist.pow(2) + ydist.pow(2)) as f32;
+        temp.sqrt()
+    }
+
+    /// Whether `other` is touching this location (diagonals count) on a board of `size` with the
+    /// given topology.
+    pub fn check_neighbour_on(&self, other: Location, topology: Topology, size: i32) -> bool {
+        if topology == Topology::Hex {
+            return HexCoord::from(*self).distance(other.into()) <= 1;
+        }
+        topology.axis_dist(self.x, other.x, size) <= 1
+            && topology.axis_dist(self.y, other.y, size) <= 1
+    }
+}
+
+/// Every location in the `width` by `height` rectangle with `corner` at its smallest `x` and `y`,
+/// a row at a time.
+pub fn rect(corner: Location, width: i32, height: i32) -> impl Iterator<Item = Location> {
+    (0..height).flat_map(move |y| (0..width).map(move |x| corner.offset_by((x, y))))
+}
+
+/// Keeps only the locations that are on `grid`.
+pub fn on_grid<'g>(
+    locs: impl IntoIterator<Item = Location> + 'g,
+    grid: &'g Grid,
+) -> impl Iterator<Item = Location> + 'g {
+    locs.into_iter().filter(|&l| grid.contains(l))
+}
+
+/// The letters a row is labelled with: `A` to `Z`, then `AA`, `AB` and so on for big boards.
+pub fn row_label(y: i32) -> String {
+    let mut label = vec![];
+    let mut n = y + 1;
+    while n > 0 {
+        n -= 1;
+        label.push(char::from(b'A' + (n % 26) as u8));
+        n /= 26;
     }
+    label.iter().rev().collect()
 }
 
+/// The row a label like `B` or `AA` stands for, ignoring case.
+fn parse_row_label(label: &str) -> Option<i32> {
+    if label.is_empty() {
+        return None;
+    }
+    let mut n: i32 = 0;
+    for c in label.chars() {
+        if !c.is_ascii_alphabetic() {
+            return None;
+        }
+        let digit = c.to_ascii_uppercase() as i32 - 'A' as i32 + 1;
+        n = n.checked_mul(26)?.checked_add(digit)?;
+    }
+    Some(n - 1)
+}
+
+/// `{}` prints `(x, y)`, and `{:#}` prints the row letter and 1-based column like the grid is
+/// labelled, so `(6, 1)` is `B7`. Layers below the surface go after an `@`, like `B7@2`, and
+/// locations off the board that have no label are written as `(x,y)`.
 impl std::fmt::Display for Location {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "({}, {})", self.x, self.y)
+        if !f.alternate() {
+            write!(f, "({}, {})", self.x, self.y)?;
+            if self.layer != 0 {
+                write!(f, " at depth {}", self.layer)?;
+            }
+            return Ok(());
+        }
+
+        if self.x >= 0 && self.y >= 0 {
+            write!(f, "{}{}", row_label(self.y), self.x + 1)?;
+        } else {
+            write!(f, "({},{})", self.x, self.y)?;
+        }
+        if self.layer != 0 {
+            write!(f, "@{}", self.layer)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("\"{0}\" isn't a cell like B7")]
+pub struct ParseLocationError(String);
+
+/// Reads the `{:#}` format back, so `B7`, `b7`, `AA12`, `B7@2` and `(-1,3)` all work.
+impl FromStr for Location {
+    type Err = ParseLocationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseLocationError(s.to_string());
+        let s = s.trim();
+        if let Some((flat, layer)) = s.split_once('@') {
+            let layer = layer.parse().map_err(|_| err())?;
+            return Ok(flat
+                .parse::<Location>()
+                .map_err(|_| err())?
+                .with_layer(layer));
+        }
+        if let Some(pair) = s.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+            let (x, y) = pair.split_once(',').ok_or_else(err)?;
+            let x = x.trim().parse().map_err(|_| err())?;
+            let y = y.trim().parse().map_err(|_| err())?;
+            return Ok(Location::new(x, y));
+        }
+
+        let split = s.find(|c: char| !c.is_ascii_alphabetic()).ok_or_else(err)?;
+        let (row, col) = s.split_at(split);
+        let y = parse_row_label(row).ok_or_else(err)?;
+        let col: i32 = col.parse().map_err(|_| err())?;
+        if col < 1 {
+            return Err(err());
+        }
+        Ok(Location::new(col - 1, y))
     }
 }
 
@@ -86,7 +359,7 @@ impl std::ops::Sub for Location {
     fn sub(self, rhs: Self) -> Self::Output {
         let x = self.x - rhs.x;
         let y = self.y - rhs.y;
-        Location::new(x, y)
+        Location::at_depth(x, y, self.layer - rhs.layer)
     }
 }
 
@@ -103,6 +376,22 @@ mod tests {
         assert_eq!(points_dist(i32::MAX, i32::MIN), 4294967295);
     }
 
+    #[test]
+    fn test_dir_from_screen() {
+        assert_eq!(Dir::from_screen("U"), Some(Dir::Down));
+        assert_eq!(Dir::from_screen("down"), Some(Dir::Up));
+        assert_eq!(Dir::from_screen("L"), Some(Dir::Left));
+        assert_eq!(Dir::from_screen("h"), Some(Dir::Right));
+        assert_eq!(Dir::from_screen("V"), Some(Dir::Up));
+        assert_eq!(Dir::from_screen("X"), None);
+        for dir in Dir::ALL {
+            assert_eq!(Dir::from_screen(dir.screen_name()), Some(dir));
+        }
+        // saved games from before ships could go any way
+        assert_eq!(serde_json::from_str::<Dir>("\"Horz\"").unwrap(), Dir::Right);
+        assert_eq!(serde_json::from_str::<Dir>("\"Vert\"").unwrap(), Dir::Up);
+    }
+
     #[test]
     fn test_dir_from_input() {
         assert_eq!(Dir::from_input("UP"), Some(Dir::Up));
@@ -130,98 +419,273 @@ mod tests {
 
     #[test]
     fn test_location_new() {
-        assert_eq!(Location::new(0, 0), Location { x: 0, y: 0 });
-        assert_eq!(Location::new(1, 3), Location { x: 1, y: 3 });
-        assert_eq!(Location::new(-1, 1), Location { x: -1, y: 1 });
+        assert_eq!(
+            Location::new(0, 0),
+            Location {
+                x: 0,
+                y: 0,
+                layer: 0
+            }
+        );
+        assert_eq!(
+            Location::new(1, 3),
+            Location {
+                x: 1,
+                y: 3,
+                layer: 0
+            }
+        );
+        assert_eq!(
+            Location::new(-1, 1),
+            Location {
+                x: -1,
+                y: 1,
+                layer: 0
+            }
+        );
+        assert_eq!(
+            Location::at_depth(1, 3, 2),
+            Location::new(1, 3).with_layer(2)
+        );
+    }
+
+    #[test]
+    fn test_location_serde_layer() {
+        let surface = serde_json::to_string(&Location::new(1, 2)).unwrap();
+        assert_eq!(surface, r#"{"x":1,"y":2}"#);
+        let deep = serde_json::to_string(&Location::at_depth(1, 2, 1)).unwrap();
+        assert_eq!(deep, r#"{"x":1,"y":2,"layer":1}"#);
+        assert_eq!(
+            serde_json::from_str::<Location>(&surface).unwrap(),
+            Location::new(1, 2)
+        );
     }
 
     #[test]
     fn test_location_move_dir() {
         {
-            let mut loc = Location { x: 2, y: 5 };
+            let mut loc = Location::new(2, 5);
             loc.move_dir(Dir::Up);
-            assert_eq!(loc, Location { x: 2, y: 6 });
+            assert_eq!(loc, Location::new(2, 6));
         }
         {
-            let mut loc = Location { x: 2, y: -5 };
+            let mut loc = Location::new(2, -5);
             loc.move_dir(Dir::Up);
-            assert_eq!(loc, Location { x: 2, y: -4 });
+            assert_eq!(loc, Location::new(2, -4));
         }
         {
-            let mut loc = Location { x: 2, y: 5 };
+            let mut loc = Location::new(2, 5);
             loc.move_dir(Dir::Down);
-            assert_eq!(loc, Location { x: 2, y: 4 });
+            assert_eq!(loc, Location::new(2, 4));
         }
         {
-            let mut loc = Location { x: 2, y: -5 };
+            let mut loc = Location::new(2, -5);
             loc.move_dir(Dir::Down);
-            assert_eq!(loc, Location { x: 2, y: -6 });
+            assert_eq!(loc, Location::new(2, -6));
         }
         {
-            let mut loc = Location { x: 2, y: 5 };
+            let mut loc = Location::new(2, 5);
             loc.move_dir(Dir::Left);
-            assert_eq!(loc, Location { x: 1, y: 5 });
+            assert_eq!(loc, Location::new(1, 5));
         }
         {
-            let mut loc = Location { x: -2, y: 5 };
+            let mut loc = Location::new(-2, 5);
             loc.move_dir(Dir::Left);
-            assert_eq!(loc, Location { x: -3, y: 5 });
+            assert_eq!(loc, Location::new(-3, 5));
         }
         {
-            let mut loc = Location { x: 2, y: 5 };
+            let mut loc = Location::new(2, 5);
             loc.move_dir(Dir::Right);
-            assert_eq!(loc, Location { x: 3, y: 5 });
+            assert_eq!(loc, Location::new(3, 5));
         }
         {
-            let mut loc = Location { x: -2, y: 5 };
+            let mut loc = Location::new(-2, 5);
             loc.move_dir(Dir::Right);
-            assert_eq!(loc, Location { x: -1, y: 5 });
+            assert_eq!(loc, Location::new(-1, 5));
         }
     }
 
     #[test]
     fn test_location_get_dist() {
         {
-            let loc1 = Location { x: 0, y: 0 };
-            let loc2 = Location { x: 0, y: 0 };
+            let loc1 = Location::new(0, 0);
+            let loc2 = Location::new(0, 0);
             assert_eq!(loc1.get_distance(loc2), 0.0);
         }
         {
-            let loc1 = Location { x: 1, y: 2 };
-            let loc2 = Location { x: 3, y: 4 };
-            assert_eq!(loc1.get_distance(loc2), 2.82842712475);
+            let loc1 = Location::new(1, 2);
+            let loc2 = Location::new(3, 4);
+            assert_eq!(loc1.get_distance(loc2), 2.828_427);
         }
         {
-            let loc1 = Location { x: -1, y: 2 };
-            let loc2 = Location { x: 3, y: -4 };
-            assert_eq!(loc1.get_distance(loc2), 7.21110255093);
+            let loc1 = Location::new(-1, 2);
+            let loc2 = Location::new(3, -4);
+            assert_eq!(loc1.get_distance(loc2), 7.211_102_5);
         }
     }
 
     #[test]
     fn test_location_check_neighbour() {
         {
-            let loc1 = Location { x: 1, y: 1 };
-            let loc2 = Location { x: 1, y: 2 };
+            let loc1 = Location::new(1, 1);
+            let loc2 = Location::new(1, 2);
             assert!(loc1.check_neighbour(loc2));
         }
         {
-            let loc1 = Location { x: 1, y: 1 };
-            let loc2 = Location { x: 2, y: 2 };
+            let loc1 = Location::new(1, 1);
+            let loc2 = Location::new(2, 2);
             assert!(loc1.check_neighbour(loc2));
         }
         {
-            let loc1 = Location { x: 1, y: 1 };
-            let loc2 = Location { x: 5, y: 5 };
+            let loc1 = Location::new(1, 1);
+            let loc2 = Location::new(5, 5);
             assert!(!(loc1.check_neighbour(loc2)));
         }
+        {
+            // same row or column, but nowhere near each other
+            let loc1 = Location::new(1, 1);
+            assert!(!loc1.check_neighbour(Location::new(5, 1)));
+            assert!(!loc1.check_neighbour(Location::new(1, 7)));
+            assert!(!loc1.check_neighbour(Location::new(2, 3)));
+        }
+    }
+
+    #[test]
+    fn test_location_neighbourhoods() {
+        let loc = Location::at_depth(3, 3, 1);
+        let orthogonal: Vec<_> = loc.orthogonal_neighbours().collect();
+        assert_eq!(
+            orthogonal,
+            vec![
+                Location::at_depth(3, 4, 1),
+                Location::at_depth(3, 2, 1),
+                Location::at_depth(2, 3, 1),
+                Location::at_depth(4, 3, 1),
+            ]
+        );
+        assert!(loc.diagonal_neighbours().all(|n| n.x != 3 && n.y != 3));
+        assert_eq!(loc.neighbours().count(), 8);
+        for n in loc.neighbours() {
+            assert!(loc.check_neighbour(n));
+            assert_eq!(loc.chebyshev_distance(n), 1);
+        }
+    }
+
+    #[test]
+    fn test_location_lines_and_rects() {
+        let line: Vec<_> = Location::new(2, 2).line(Dir::Left, 3).collect();
+        assert_eq!(
+            line,
+            vec![
+                Location::new(2, 2),
+                Location::new(1, 2),
+                Location::new(0, 2)
+            ]
+        );
+        assert_eq!(Location::new(0, 0).line(Dir::Up, 0).count(), 0);
+
+        let cells: Vec<_> = rect(Location::new(1, 1), 2, 2).collect();
+        assert_eq!(
+            cells,
+            vec![
+                Location::new(1, 1),
+                Location::new(2, 1),
+                Location::new(1, 2),
+                Location::new(2, 2)
+            ]
+        );
+
+        let grid = Grid::new(3);
+        let on: Vec<_> = on_grid(Location::new(0, 0).neighbours(), &grid).collect();
+        assert_eq!(on.len(), 3);
+        assert!(!grid.contains(Location::at_depth(0, 0, 1)));
+    }
+
+    #[test]
+    fn test_location_distances() {
+        let a = Location::new(1, 2);
+        let b = Location::new(4, -2);
+        assert_eq!(a.manhattan_distance(b), 7);
+        assert_eq!(a.chebyshev_distance(b), 4);
+        assert_eq!(b.manhattan_distance(a), 7);
+        assert_eq!(a.chebyshev_distance(a), 0);
+    }
+
+    #[test]
+    fn test_topology_wrap() {
+        let loc = Location::new(-1, 10);
+        assert_eq!(Topology::Flat.wrap(loc, 10), loc);
+        assert_eq!(Topology::Torus.wrap(loc, 10), Location::new(9, 0));
+        assert_eq!(
+            Topology::Torus.wrap(Location::new(3, 4), 10),
+            Location::new(3, 4)
+        );
+    }
+
+    #[test]
+    fn test_location_topology_aware() {
+        let corner = Location::new(0, 0);
+        let opposite = Location::new(9, 9);
+        assert!(!corner.check_neighbour_on(opposite, Topology::Flat, 10));
+        assert!(corner.check_neighbour_on(opposite, Topology::Torus, 10));
+        assert!(!corner.check_neighbour_on(Location::new(0, 5), Topology::Torus, 10));
+        assert!(!corner.check_neighbour_on(Location::new(5, 0), Topology::Flat, 10));
+
+        assert_eq!(
+            corner.get_distance_on(Location::new(8, 0), Topology::Flat, 10),
+            8.0
+        );
+        assert_eq!(
+            corner.get_distance_on(Location::new(8, 0), Topology::Torus, 10),
+            2.0
+        );
+        assert_eq!(
+            Location::new(1, 2).get_distance_on(Location::new(3, 4), Topology::Torus, 10),
+            2.828_427
+        );
+
+        assert!(corner.check_neighbour_on(Location::new(1, 0), Topology::Hex, 10));
+        assert!(!corner.check_neighbour_on(Location::new(1, 1), Topology::Hex, 10));
+        assert_eq!(
+            corner.get_distance_on(Location::new(2, 2), Topology::Hex, 10),
+            4.0
+        );
     }
 
     #[test]
     fn test_location_display() {
-        assert_eq!(format!("{}", Location { x: 2, y: 5 }), "(2, 5)");
-        assert_eq!(format!("{}", Location { x: -2, y: 5 }), "(-2, 5)");
-        assert_eq!(format!("{}", Location { x: 8, y: -1 }), "(8, -1)");
-        assert_eq!(format!("{}", Location { x: -120, y: -800 }), "(-120, -800)");
+        assert_eq!(format!("{}", Location::new(2, 5)), "(2, 5)");
+        assert_eq!(format!("{}", Location::new(-2, 5)), "(-2, 5)");
+        assert_eq!(format!("{}", Location::new(8, -1)), "(8, -1)");
+        assert_eq!(format!("{}", Location::new(-120, -800)), "(-120, -800)");
+        assert_eq!(
+            format!("{}", Location::at_depth(3, 4, 1)),
+            "(3, 4) at depth 1"
+        );
+    }
+
+    #[test]
+    fn test_location_letters() {
+        assert_eq!(format!("{:#}", Location::new(6, 1)), "B7");
+        assert_eq!(format!("{:#}", Location::new(9, 9)), "J10");
+        assert_eq!(format!("{:#}", Location::new(0, 26)), "AA1");
+        assert_eq!(format!("{:#}", Location::at_depth(6, 1, 2)), "B7@2");
+        assert_eq!(format!("{:#}", Location::new(-1, -1)), "(-1,-1)");
+        assert_eq!(row_label(25), "Z");
+        assert_eq!(row_label(27), "AB");
+        assert_eq!(row_label(26 * 27), "AAA");
+
+        assert_eq!("B7".parse(), Ok(Location::new(6, 1)));
+        assert_eq!(" j10 ".parse(), Ok(Location::new(9, 9)));
+        assert_eq!("AB3".parse(), Ok(Location::new(2, 27)));
+        assert_eq!("B7@2".parse(), Ok(Location::at_depth(6, 1, 2)));
+        assert_eq!("(-1,-1)".parse(), Ok(Location::new(-1, -1)));
+        for bad in ["7B", "", "B", "B0", "B7@", "B-1", "(1)", "B7x"] {
+            assert!(bad.parse::<Location>().is_err(), "{bad}");
+        }
+        for y in [0, 25, 26, 700, 701] {
+            let loc = Location::new(4, y);
+            assert_eq!(format!("{loc:#}").parse(), Ok(loc));
+        }
     }
 }
@@ -0,0 +1,822 @@
+//! A plain-text notation for recording games, loosely modelled on chess PGN.
+//!
+//! ```text
+//! [Size "10"]
+//! [Seed "1234"]
+//! [Rules "handicap"]
+//! [Player1 "Alice"]
+//! [Player2 "Bob"]
+//! [Result "P1"]
+//! [Player1Shots "2"]
+//!
+//! P1 place A1 H
+//! P2 random
+//! start
+//! P1 B7 hit
+//! P2 C3 miss
+//! P1 B8 hit sunk:Destroyer
+//! ```
+//!
+//! Coordinates are written the same way the grid is drawn, which is `Location`'s `{:#}` format:
+//! the row letter then the 1-based column number, with shots that land off the board written as
+//! a raw `(x,y)` pair. Shot results are optional when reading, but are checked if they are given.
+//!
+//! `Size` is the size of both grids. Handicap games say how each player's rules differ from that
+//! with the `Player1Size`, `Player1Fleet` and `Player1Shots` tags (and the same for player 2),
+//! where the fleet is a list of ship classes separated by spaces. Boards that wrap around their
+//! edges have a `[Topology "torus"]` tag, and hex boards have `[Topology "hex"]`. Ships on hex
+//! boards are placed with one of the six directions `E`, `W`, `NE`, `NW`, `SE` and `SW`.
+//!
+//! Boards with layers below the surface have a `Depths` tag saying how many there are, and
+//! coordinates below the surface have the layer after an `@`, so `B7@1` is B7 one layer down.
+//!
+//! Ship classes that take more than one hit per cell are listed in an `Armour` tag, like
+//! `[Armour "Battleship:2 Carrier:3"]`. Shots that hit a cell without destroying it are written
+//! with `armour` instead of `hit`.
+//!
+//! When players can repair their ships, the `Repairs` tag says how many repairs each player gets
+//! and `[HiddenRepairs "yes"]` means the opponent isn't told about them. A repair is written like
+//! `P2 repair C3`.
+//!
+//! Decoys go in `Player1Decoys` and `Player2Decoys`, listed like a fleet and placed after it, and
+//! `[RevealDecoys "yes"]` means sinking one is written as `hit decoy`. A `Disclosure` tag of
+//! `hits` or `sinks` limits what shots reveal, and with `sinks` a sunk ship is just `hit sunk`.
+
+use std::{collections::HashMap, str::FromStr};
+
+use thiserror::Error;
+
+use crate::{
+    game::{
+        Action, Disclosure, Game, Player, PlayerRules, ReplayError, Rules, RulesError, SavedGame,
+        ShotOutcome, SHIPS_ORDER,
+    },
+    hex::HexDir,
+    location::{Dir, Topology},
+    ship::ShipClass,
+};
+
+#[derive(Debug, Error)]
+pub enum NotationError {
+    #[error("line {0} could not be understood")]
+    InvalidLine(usize),
+    #[error("the {0} tag is missing from the header")]
+    MissingTag(&'static str),
+    #[error("the {0} tag has an invalid value")]
+    InvalidTag(&'static str),
+    #[error("the recorded result of move {0} does not match the game")]
+    OutcomeMismatch(usize),
+    #[error(transparent)]
+    Rules(#[from] RulesError),
+    #[error(transparent)]
+    Replay(#[from] ReplayError),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub rules: Rules,
+    pub seed: u64,
+    pub players: [String; 2],
+    pub result: Option<Player>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Move {
+    pub action: Action,
+    pub outcome: Option<ShotOutcome>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    pub header: Header,
+    pub moves: Vec<Move>,
+}
+
+impl Record {
+    /// Records every action taken so far in `game`, along with the result of each shot.
+    pub fn from_game(game: &Game, players: [String; 2]) -> Self {
+        let saved = game.to_saved();
+        // replaying the game is the easiest way to find out what each shot did
+        let mut replay = Game::with_rules(saved.rules.clone(), saved.seed);
+        let mut moves = vec![];
+        for action in saved.actions {
+            let outcome = match action {
+                Action::Guess { player, loc } => replay.guess_position(player, loc).ok(),
+                Action::AddShip { player, start, dir } => {
+                    let _ = replay.add_ship(player, start, dir);
+                    None
+                }
+                Action::AddHexShip { player, start, dir } => {
+                    let _ = replay.add_hex_ship(player, start.into(), start.layer, dir);
+                    None
+                }
+                Action::AddRandomShips { player } => {
+                    let _ = replay.add_random_ships(player);
+                    None
+                }
+                Action::StartPlaying => {
+                    let _ = replay.change_to_playing();
+                    None
+                }
+                Action::Repair { player, loc } => {
+                    let _ = replay.repair(player, loc);
+                    None
+                }
+            };
+            moves.push(Move { action, outcome });
+        }
+
+        Self {
+            header: Header {
+                rules: saved.rules,
+                seed: saved.seed,
+                players,
+                result: game.winner(),
+            },
+            moves,
+        }
+    }
+
+    /// Rebuilds the recorded game, checking the rules and any shot results written in the record.
+    pub fn to_game(&self) -> Result<Game, NotationError> {
+        self.header.rules.check()?;
+        let saved = SavedGame {
+            seed: self.header.seed,
+            rules: self.header.rules.clone(),
+            actions: self.moves.iter().map(|m| m.action.clone()).collect(),
+        };
+        let game = Game::from_saved(&saved)?;
+
+        let replayed = Record::from_game(&game, self.header.players.clone());
+        for (i, (ours, theirs)) in self.moves.iter().zip(replayed.moves).enumerate() {
+            if ours.outcome.is_some() && ours.outcome != theirs.outcome {
+                return Err(NotationError::OutcomeMismatch(i + 1));
+            }
+        }
+        Ok(game)
+    }
+}
+
+/// Which player a `Player1...`/`Player2...` tag is about, as an index.
+fn tag_player(name: &str) -> usize {
+    if name.starts_with("Player1") {
+        0
+    } else {
+        1
+    }
+}
+
+fn player_str(player: Player) -> String {
+    format!("P{}", player.num())
+}
+
+fn parse_player(s: &str) -> Option<Player> {
+    match s {
+        "P1" => Some(Player::Player1),
+        "P2" => Some(Player::Player2),
+        _ => None,
+    }
+}
+
+impl std::fmt::Display for Move {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.action {
+            Action::AddShip { player, start, dir } => {
+                // H and V are kept from when ships could only go right or down
+                let dir = match dir {
+                    Dir::Right => "H",
+                    Dir::Up => "V",
+                    _ => dir.screen_name(),
+                };
+                write!(f, "{} place {:#} {}", player_str(player), start, dir)
+            }
+            Action::AddHexShip { player, start, dir } => {
+                write!(f, "{} place {:#} {}", player_str(player), start, dir.name())
+            }
+            Action::AddRandomShips { player } => write!(f, "{} random", player_str(player)),
+            Action::StartPlaying => write!(f, "start"),
+            Action::Repair { player, loc } => {
+                write!(f, "{} repair {:#}", player_str(player), loc)
+            }
+            Action::Guess { player, loc } => {
+                write!(f, "{} {:#}", player_str(player), loc)?;
+                match self.outcome {
+                    Some(ShotOutcome::Miss) => write!(f, " miss"),
+                    Some(ShotOutcome::Armoured) => write!(f, " armour"),
+                    Some(ShotOutcome::Hit) => write!(f, " hit"),
+                    Some(ShotOutcome::Sunk(class)) => write!(f, " hit sunk:{}", class),
+                    Some(ShotOutcome::SunkUnnamed) => write!(f, " hit sunk"),
+                    Some(ShotOutcome::Decoy) => write!(f, " hit decoy"),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+fn parse_move(line: &str) -> Option<Move> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let action = match words[..] {
+        ["start"] => Action::StartPlaying,
+        [p, "random"] => Action::AddRandomShips {
+            player: parse_player(p)?,
+        },
+        [p, "repair", loc] => Action::Repair {
+            player: parse_player(p)?,
+            loc: loc.parse().ok()?,
+        },
+        [p, "place", start, dir] => {
+            let player = parse_player(p)?;
+            let start = start.parse().ok()?;
+            match Dir::from_screen(dir) {
+                Some(dir) => Action::AddShip { player, start, dir },
+                None => Action::AddHexShip {
+                    player,
+                    start,
+                    dir: HexDir::from_input(dir)?,
+                },
+            }
+        }
+        [p, loc, ..] => Action::Guess {
+            player: parse_player(p)?,
+            loc: loc.parse().ok()?,
+        },
+        _ => return None,
+    };
+
+    let outcome = match (&action, &words[..]) {
+        (Action::Guess { .. }, [_, _]) => None,
+        (Action::Guess { .. }, [_, _, "miss"]) => Some(ShotOutcome::Miss),
+        (Action::Guess { .. }, [_, _, "armour"]) => Some(ShotOutcome::Armoured),
+        (Action::Guess { .. }, [_, _, "hit"]) => Some(ShotOutcome::Hit),
+        (Action::Guess { .. }, [_, _, "hit", "sunk"]) => Some(ShotOutcome::SunkUnnamed),
+        (Action::Guess { .. }, [_, _, "hit", "decoy"]) => Some(ShotOutcome::Decoy),
+        (Action::Guess { .. }, [_, _, "hit", sunk]) => {
+            let class = ShipClass::from_name(sunk.strip_prefix("sunk:")?)?;
+            Some(ShotOutcome::Sunk(class))
+        }
+        (Action::Guess { .. }, _) => return None,
+        _ => None,
+    };
+
+    Some(Move { action, outcome })
+}
+
+impl std::fmt::Display for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let header = &self.header;
+        let size = header.rules.players[0].size;
+        let standard = header.rules.players == Rules::standard(size).players;
+        writeln!(f, "[Size \"{}\"]", size)?;
+        writeln!(f, "[Seed \"{}\"]", header.seed)?;
+        let rules = if standard { "standard" } else { "handicap" };
+        writeln!(f, "[Rules \"{}\"]", rules)?;
+        if header.rules.topology != Topology::Flat {
+            writeln!(f, "[Topology \"{}\"]", header.rules.topology.name())?;
+        }
+        if header.rules.depths != 0 {
+            writeln!(f, "[Depths \"{}\"]", header.rules.depths)?;
+        }
+        if !header.rules.armour.is_empty() {
+            let armour: Vec<String> = SHIPS_ORDER
+                .iter()
+                .filter_map(|c| Some(format!("{}:{}", c, header.rules.armour.get(c)?)))
+                .collect();
+            writeln!(f, "[Armour \"{}\"]", armour.join(" "))?;
+        }
+        if header.rules.repairs != 0 {
+            writeln!(f, "[Repairs \"{}\"]", header.rules.repairs)?;
+        }
+        if header.rules.hide_repairs {
+            writeln!(f, "[HiddenRepairs \"yes\"]")?;
+        }
+        if header.rules.disclosure != Disclosure::default() {
+            writeln!(f, "[Disclosure \"{}\"]", header.rules.disclosure.name())?;
+        }
+        if header.rules.reveal_decoys {
+            writeln!(f, "[RevealDecoys \"yes\"]")?;
+        }
+        writeln!(f, "[Player1 \"{}\"]", header.players[0])?;
+        writeln!(f, "[Player2 \"{}\"]", header.players[1])?;
+        let result = match header.result {
+            Some(p) => player_str(p),
+            None => "*".to_string(),
+        };
+        writeln!(f, "[Result \"{}\"]", result)?;
+
+        let default = PlayerRules::standard(size);
+        for (i, rules) in header.rules.players.iter().enumerate() {
+            if rules.size != default.size {
+                writeln!(f, "[Player{}Size \"{}\"]", i + 1, rules.size)?;
+            }
+            if rules.fleet != default.fleet {
+                let fleet: Vec<&str> = rules.fleet.iter().map(|c| c.name()).collect();
+                writeln!(f, "[Player{}Fleet \"{}\"]", i + 1, fleet.join(" "))?;
+            }
+            if rules.shots_per_turn != default.shots_per_turn {
+                writeln!(f, "[Player{}Shots \"{}\"]", i + 1, rules.shots_per_turn)?;
+            }
+            if !rules.decoys.is_empty() {
+                let decoys: Vec<&str> = rules.decoys.iter().map(|c| c.name()).collect();
+                writeln!(f, "[Player{}Decoys \"{}\"]", i + 1, decoys.join(" "))?;
+            }
+        }
+        writeln!(f)?;
+        for m in &self.moves {
+            writeln!(f, "{}", m)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Record {
+    type Err = NotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut size = None;
+        let mut player_sizes = [None, None];
+        let mut fleets = [None, None];
+        let mut shots = [None, None];
+        let mut decoys = [None, None];
+        let mut topology = Topology::Flat;
+        let mut depths = 0;
+        let mut armour = HashMap::new();
+        let mut repairs = 0;
+        let mut hide_repairs = false;
+        let mut reveal_decoys = false;
+        let mut disclosure = Disclosure::default();
+        let mut seed = None;
+        let mut players = [String::from("Player 1"), String::from("Player 2")];
+        let mut result = None;
+        let mut moves = vec![];
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(tag) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                let (name, value) = tag
+                    .split_once(' ')
+                    .ok_or(NotationError::InvalidLine(i + 1))?;
+                let value = value
+                    .trim()
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .ok_or(NotationError::InvalidLine(i + 1))?;
+                match name {
+                    "Size" => {
+                        size = Some(
+                            value
+                                .parse()
+                                .map_err(|_| NotationError::InvalidTag("Size"))?,
+                        )
+                    }
+                    "Seed" => {
+                        seed = Some(
+                            value
+                                .parse()
+                                .map_err(|_| NotationError::InvalidTag("Seed"))?,
+                        )
+                    }
+                    "Rules" if value != "standard" && value != "handicap" => {
+                        return Err(NotationError::InvalidTag("Rules"))
+                    }
+                    "Armour" => {
+                        for entry in value.split_whitespace() {
+                            let (class, hits) = entry
+                                .split_once(':')
+                                .and_then(|(c, h)| {
+                                    Some((ShipClass::from_name(c)?, h.parse().ok()?))
+                                })
+                                .ok_or(NotationError::InvalidTag("Armour"))?;
+                            armour.insert(class, hits);
+                        }
+                    }
+                    "Repairs" => {
+                        repairs = value
+                            .parse()
+                            .map_err(|_| NotationError::InvalidTag("Repairs"))?
+                    }
+                    "HiddenRepairs" => hide_repairs = value == "yes",
+                    "RevealDecoys" => reveal_decoys = value == "yes",
+                    "Disclosure" => {
+                        disclosure = Disclosure::from_name(value)
+                            .ok_or(NotationError::InvalidTag("Disclosure"))?
+                    }
+                    "Depths" => {
+                        depths = value
+                            .parse()
+                            .map_err(|_| NotationError::InvalidTag("Depths"))?
+                    }
+                    "Topology" => {
+                        topology = Topology::from_name(value)
+                            .ok_or(NotationError::InvalidTag("Topology"))?
+                    }
+                    "Player1Size" | "Player2Size" => {
+                        player_sizes[tag_player(name)] = Some(
+                            value
+                                .parse()
+                                .map_err(|_| NotationError::InvalidTag("Size"))?,
+                        )
+                    }
+                    "Player1Fleet" | "Player2Fleet" => {
+                        let fleet: Option<Vec<ShipClass>> =
+                            value.split_whitespace().map(ShipClass::from_name).collect();
+                        fleets[tag_player(name)] =
+                            Some(fleet.ok_or(NotationError::InvalidTag("Fleet"))?)
+                    }
+                    "Player1Decoys" | "Player2Decoys" => {
+                        let classes: Option<Vec<ShipClass>> =
+                            value.split_whitespace().map(ShipClass::from_name).collect();
+                        decoys[tag_player(name)] =
+                            Some(classes.ok_or(NotationError::InvalidTag("Decoys"))?)
+                    }
+                    "Player1Shots" | "Player2Shots" => {
+                        shots[tag_player(name)] = Some(
+                            value
+                                .parse()
+                                .map_err(|_| NotationError::InvalidTag("Shots"))?,
+                        )
+                    }
+                    "Player1" => players[0] = value.to_string(),
+                    "Player2" => players[1] = value.to_string(),
+                    "Result" => result = parse_player(value),
+                    // unknown tags are kept by people for their own notes, so just skip them
+                    _ => {}
+                }
+                continue;
+            }
+
+            moves.push(parse_move(line).ok_or(NotationError::InvalidLine(i + 1))?);
+        }
+
+        let size = size.ok_or(NotationError::MissingTag("Size"))?;
+        let mut rules = Rules::standard(size);
+        rules.topology = topology;
+        rules.depths = depths;
+        rules.armour = armour;
+        rules.repairs = repairs;
+        rules.hide_repairs = hide_repairs;
+        rules.reveal_decoys = reveal_decoys;
+        rules.disclosure = disclosure;
+        for (i, player) in rules.players.iter_mut().enumerate() {
+            if let Some(size) = player_sizes[i] {
+                player.size = size;
+            }
+            if let Some(fleet) = fleets[i].take() {
+                player.fleet = fleet;
+            }
+            if let Some(shots) = shots[i] {
+                player.shots_per_turn = shots;
+            }
+            if let Some(classes) = decoys[i].take() {
+                player.decoys = classes;
+            }
+        }
+
+        Ok(Self {
+            header: Header {
+                rules,
+                seed: seed.ok_or(NotationError::MissingTag("Seed"))?,
+                players,
+                result,
+            },
+            moves,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{location::Location, ship::ShipDir};
+
+    #[test]
+    fn test_coords() {
+        let m = parse_move("P1 j10 miss").unwrap();
+        assert_eq!(m.to_string(), "P1 J10 miss");
+        let m = parse_move("P2 (-1,-1)").unwrap();
+        assert_eq!(
+            m.action,
+            Action::Guess {
+                player: Player::Player2,
+                loc: Location::new(-1, -1)
+            }
+        );
+        assert_eq!(m.to_string(), "P2 (-1,-1)");
+        assert!(parse_move("P1 7B").is_none());
+    }
+
+    #[test]
+    fn test_parse_move() {
+        assert_eq!(
+            parse_move("P2 C3 miss"),
+            Some(Move {
+                action: Action::Guess {
+                    player: Player::Player2,
+                    loc: Location::new(2, 2),
+                },
+                outcome: Some(ShotOutcome::Miss),
+            })
+        );
+        assert_eq!(
+            parse_move("P1 B8 hit sunk:Destroyer"),
+            Some(Move {
+                action: Action::Guess {
+                    player: Player::Player1,
+                    loc: Location::new(7, 1),
+                },
+                outcome: Some(ShotOutcome::Sunk(ShipClass::Destroyer)),
+            })
+        );
+        assert_eq!(
+            parse_move("P1 place A1 V"),
+            Some(Move {
+                action: Action::AddShip {
+                    player: Player::Player1,
+                    start: Location::new(0, 0),
+                    dir: Dir::Up,
+                },
+                outcome: None,
+            })
+        );
+        assert_eq!(parse_move("P3 A1 hit"), None);
+        assert_eq!(parse_move("P1 A1 hit sunk:Rowboat"), None);
+        assert_eq!(parse_move("P1 A1 splash"), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut game = Game::with_seed(10, 7);
+        game.add_random_ships(Player::Player1).unwrap();
+        for y in 0..5 {
+            game.add_ship(Player::Player2, Location::new(0, y), ShipDir::Horz)
+                .unwrap();
+        }
+        game.change_to_playing().unwrap();
+        for x in 0..2 {
+            game.guess_position(Player::Player1, Location::new(x, 0))
+                .unwrap();
+            game.guess_position(Player::Player2, Location::new(x, 9))
+                .unwrap();
+        }
+
+        let record = Record::from_game(&game, ["Alice".to_string(), "Bob".to_string()]);
+        let text = record.to_string();
+        assert!(text.contains("P1 A2 hit sunk:Destroyer\n"));
+        assert!(text.contains("P2 J1 "));
+
+        let parsed: Record = text.parse().unwrap();
+        assert_eq!(parsed, record);
+        let loaded = parsed.to_game().unwrap();
+        assert_eq!(loaded.to_saved(), game.to_saved());
+    }
+
+    #[test]
+    fn test_handicap_round_trip() {
+        let mut rules = Rules::standard(10);
+        rules.players[1].size = 8;
+        rules.players[1].fleet = vec![ShipClass::Destroyer, ShipClass::Cruiser];
+        rules.players[0].shots_per_turn = 2;
+        let mut game = Game::with_rules(rules, 3);
+        game.add_random_ships(Player::Player1).unwrap();
+        game.add_random_ships(Player::Player2).unwrap();
+        game.change_to_playing().unwrap();
+
+        let record = Record::from_game(&game, ["Alice".to_string(), "Bob".to_string()]);
+        let text = record.to_string();
+        assert!(text.contains("[Rules \"handicap\"]\n"));
+        assert!(text.contains("[Player2Size \"8\"]\n"));
+        assert!(text.contains("[Player2Fleet \"Destroyer Cruiser\"]\n"));
+        assert!(text.contains("[Player1Shots \"2\"]\n"));
+        assert!(!text.contains("Player1Size"));
+
+        let parsed: Record = text.parse().unwrap();
+        assert_eq!(parsed, record);
+        assert_eq!(parsed.to_game().unwrap().to_saved(), game.to_saved());
+    }
+
+    #[test]
+    fn test_torus_tag() {
+        let mut rules = Rules::standard(10);
+        rules.topology = Topology::Torus;
+        let mut game = Game::with_rules(rules, 4);
+        game.add_ship(Player::Player1, Location::new(9, 9), ShipDir::Horz)
+            .unwrap();
+
+        let record = Record::from_game(&game, ["A".to_string(), "B".to_string()]);
+        let text = record.to_string();
+        assert!(text.contains("[Rules \"standard\"]\n[Topology \"torus\"]\n"));
+        let parsed: Record = text.parse().unwrap();
+        assert_eq!(parsed.to_game().unwrap().to_saved(), game.to_saved());
+        assert!(matches!(
+            "[Size \"10\"]\n[Seed \"1\"]\n[Topology \"sphere\"]\n".parse::<Record>(),
+            Err(NotationError::InvalidTag("Topology"))
+        ));
+    }
+
+    #[test]
+    fn test_four_direction_moves() {
+        let m = parse_move("P1 place C3 U").unwrap();
+        assert_eq!(
+            m.action,
+            Action::AddShip {
+                player: Player::Player1,
+                start: Location::new(2, 2),
+                dir: Dir::Down
+            }
+        );
+        assert_eq!(m.to_string(), "P1 place C3 U");
+        assert_eq!(
+            parse_move("P1 place C3 l").unwrap().to_string(),
+            "P1 place C3 L"
+        );
+        // right and down are still written the old way
+        assert_eq!(
+            parse_move("P1 place C3 R").unwrap().to_string(),
+            "P1 place C3 H"
+        );
+        assert_eq!(
+            parse_move("P1 place C3 D").unwrap().to_string(),
+            "P1 place C3 V"
+        );
+    }
+
+    #[test]
+    fn test_hex_moves() {
+        let m = parse_move("P2 place C1 sw").unwrap();
+        assert_eq!(
+            m.action,
+            Action::AddHexShip {
+                player: Player::Player2,
+                start: Location::new(0, 2),
+                dir: HexDir::SouthWest
+            }
+        );
+        assert_eq!(m.to_string(), "P2 place C1 SW");
+        assert!(parse_move("P2 place C1 N").is_none());
+    }
+
+    #[test]
+    fn test_depths() {
+        assert_eq!(parse_move("P1 B7@2").unwrap().to_string(), "P1 B7@2");
+        assert!(parse_move("P1 B7@").is_none());
+
+        let mut rules = Rules::standard(10);
+        rules.depths = 1;
+        let mut game = Game::with_rules(rules, 4);
+        game.add_random_ships(Player::Player1).unwrap();
+        game.add_random_ships(Player::Player2).unwrap();
+        game.change_to_playing().unwrap();
+        game.guess_position(Player::Player1, Location::at_depth(0, 0, 1))
+            .unwrap();
+
+        let record = Record::from_game(&game, ["A".to_string(), "B".to_string()]);
+        let text = record.to_string();
+        assert!(text.contains("[Depths \"1\"]\n"));
+        assert!(text.contains("P1 A1@1 "));
+        let parsed: Record = text.parse().unwrap();
+        assert_eq!(parsed.to_game().unwrap().to_saved(), game.to_saved());
+    }
+
+    #[test]
+    fn test_armour() {
+        let mut rules = Rules::standard(10);
+        rules.armour.insert(ShipClass::Carrier, 3);
+        rules.armour.insert(ShipClass::Destroyer, 2);
+        let mut game = Game::with_rules(rules, 4);
+        for y in 0..5 {
+            game.add_ship(Player::Player1, Location::new(0, y), ShipDir::Horz)
+                .unwrap();
+        }
+        game.add_random_ships(Player::Player2).unwrap();
+        game.change_to_playing().unwrap();
+        let p2_destroyer = game.get_grid(Player::Player2).ships[0].get_coords()[0];
+        game.guess_position(Player::Player1, p2_destroyer).unwrap();
+
+        let record = Record::from_game(&game, ["A".to_string(), "B".to_string()]);
+        let text = record.to_string();
+        assert!(text.contains("[Armour \"Destroyer:2 Carrier:3\"]\n"));
+        assert!(text.contains(&format!("P1 {:#} armour\n", p2_destroyer)));
+        let parsed: Record = text.parse().unwrap();
+        assert_eq!(parsed, record);
+        assert_eq!(parsed.to_game().unwrap().to_saved(), game.to_saved());
+    }
+
+    #[test]
+    fn test_repairs() {
+        let mut rules = Rules::standard(10);
+        rules.repairs = 2;
+        rules.hide_repairs = true;
+        let mut game = Game::with_rules(rules, 4);
+        for p in [Player::Player1, Player::Player2] {
+            for y in 0..5 {
+                game.add_ship(p, Location::new(0, y), ShipDir::Horz)
+                    .unwrap();
+            }
+        }
+        game.change_to_playing().unwrap();
+        game.guess_position(Player::Player1, Location::new(0, 2))
+            .unwrap();
+        game.repair(Player::Player2, Location::new(0, 2)).unwrap();
+
+        let record = Record::from_game(&game, ["A".to_string(), "B".to_string()]);
+        let text = record.to_string();
+        assert!(text.contains("[Repairs \"2\"]\n[HiddenRepairs \"yes\"]\n"));
+        assert!(text.ends_with("P1 C1 hit\nP2 repair C1\n"));
+        let parsed: Record = text.parse().unwrap();
+        assert_eq!(parsed, record);
+        assert_eq!(parsed.to_game().unwrap().to_saved(), game.to_saved());
+    }
+
+    #[test]
+    fn test_decoys() {
+        let mut rules = Rules::standard(10);
+        rules.players[1].decoys = vec![ShipClass::Destroyer];
+        rules.reveal_decoys = true;
+        let mut game = Game::with_rules(rules, 4);
+        game.add_random_ships(Player::Player1).unwrap();
+        for y in 0..6 {
+            game.add_ship(Player::Player2, Location::new(0, y), ShipDir::Horz)
+                .unwrap();
+        }
+        game.change_to_playing().unwrap();
+        for x in 0..2 {
+            game.guess_position(Player::Player1, Location::new(x, 5))
+                .unwrap();
+            game.guess_position(Player::Player2, Location::new(x, 9))
+                .unwrap();
+        }
+
+        let record = Record::from_game(&game, ["A".to_string(), "B".to_string()]);
+        let text = record.to_string();
+        assert!(text.contains("[RevealDecoys \"yes\"]\n"));
+        assert!(text.contains("[Player2Decoys \"Destroyer\"]\n"));
+        assert!(text.contains("P1 F2 hit decoy\n"));
+        let parsed: Record = text.parse().unwrap();
+        assert_eq!(parsed, record);
+        assert_eq!(parsed.to_game().unwrap().to_saved(), game.to_saved());
+    }
+
+    #[test]
+    fn test_disclosure() {
+        let mut rules = Rules::standard(10);
+        rules.disclosure = Disclosure::Sunk;
+        let mut game = Game::with_rules(rules, 4);
+        game.add_random_ships(Player::Player1).unwrap();
+        for y in 0..5 {
+            game.add_ship(Player::Player2, Location::new(0, y), ShipDir::Horz)
+                .unwrap();
+        }
+        game.change_to_playing().unwrap();
+        for x in 0..2 {
+            game.guess_position(Player::Player1, Location::new(x, 0))
+                .unwrap();
+            game.guess_position(Player::Player2, Location::new(x, 9))
+                .unwrap();
+        }
+
+        let record = Record::from_game(&game, ["A".to_string(), "B".to_string()]);
+        let text = record.to_string();
+        assert!(text.contains("[Disclosure \"sinks\"]\n"));
+        assert!(text.contains("P1 A2 hit sunk\n"));
+        let parsed: Record = text.parse().unwrap();
+        assert_eq!(parsed, record);
+        assert_eq!(parsed.to_game().unwrap().to_saved(), game.to_saved());
+    }
+
+    #[test]
+    fn test_outcome_mismatch() {
+        let text = "[Size \"10\"]\n[Seed \"1\"]\n\n\
+                    P1 place A1 H\nP1 place B1 H\nP1 place C1 H\nP1 place D1 H\nP1 place E1 H\n\
+                    P2 place A1 H\nP2 place B1 H\nP2 place C1 H\nP2 place D1 H\nP2 place E1 H\n\
+                    start\nP1 J10 hit\n";
+        let record: Record = text.parse().unwrap();
+        assert!(matches!(
+            record.to_game(),
+            Err(NotationError::OutcomeMismatch(12))
+        ));
+    }
+
+    #[test]
+    fn test_unplayable_rules() {
+        for text in [
+            "[Size \"0\"]\n[Seed \"1\"]\n",
+            "[Size \"100000\"]\n[Seed \"1\"]\n",
+            "[Size \"10\"]\n[Seed \"1\"]\n[Player1Shots \"0\"]\n",
+        ] {
+            let record: Record = text.parse().unwrap();
+            assert!(matches!(record.to_game(), Err(NotationError::Rules(_))));
+        }
+    }
+
+    #[test]
+    fn test_missing_tag() {
+        assert!(matches!(
+            "[Seed \"1\"]\nstart\n".parse::<Record>(),
+            Err(NotationError::MissingTag("Size"))
+        ));
+    }
+}
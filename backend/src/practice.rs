@@ -0,0 +1,231 @@
+//! Single player practice: the computer hides a fleet and the player tries to sink all of it in
+//! as few shots as possible.
+
+use std::path::{Path, PathBuf};
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    game::{ShotOutcome, SHIPS_ORDER},
+    location::Location,
+    ship::Grid,
+};
+
+/// How many scores are kept in the personal best table.
+const BEST_SCORES_LEN: usize = 10;
+
+#[derive(Debug, Error)]
+pub enum PracticeError {
+    #[error("that shot is not on the board")]
+    OutOfBounds,
+    #[error("that cell has already been shot at")]
+    AlreadyShot,
+    #[error("the whole fleet has already been sunk")]
+    Cleared,
+}
+
+#[derive(Clone, Debug)]
+pub struct Practice {
+    grid: Grid,
+    seed: u64,
+    shots: u32,
+}
+
+impl Practice {
+    pub fn new(size: i32) -> Self {
+        Self::with_seed(size, rand::random())
+    }
+
+    pub fn with_seed(size: i32, seed: u64) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let mut grid = Grid::new(size);
+        for class in SHIPS_ORDER {
+            grid.add_random_ship(class.size(), &mut rng)
+                .expect("the standard fleet always fits on the board");
+        }
+        Self {
+            grid,
+            seed,
+            shots: 0,
+        }
+    }
+
+    pub fn get_grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn shots(&self) -> u32 {
+        self.shots
+    }
+
+    pub fn is_cleared(&self) -> bool {
+        self.grid.check_loss()
+    }
+
+    /// Shoots at `coords`. Shots that are off the board or repeated are refused and don't count.
+    pub fn fire(&mut self, coords: Location) -> Result<ShotOutcome, PracticeError> {
+        if self.is_cleared() {
+            return Err(PracticeError::Cleared);
+        }
+        let size = self.grid.size;
+        if coords.x < 0 || coords.y < 0 || coords.x >= size || coords.y >= size {
+            return Err(PracticeError::OutOfBounds);
+        }
+        if self.grid.wrong_guesses.contains(&coords) || self.grid.get_all_found().contains(&coords)
+        {
+            return Err(PracticeError::AlreadyShot);
+        }
+
+        self.shots += 1;
+        Ok(ShotOutcome::fire(
+            &mut self.grid,
+            coords,
+            &SHIPS_ORDER,
+            false,
+        ))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BestScore {
+    pub shots: u32,
+    pub seed: u64,
+}
+
+/// The player's best practice games, fewest shots first.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BestScores {
+    pub scores: Vec<BestScore>,
+}
+
+impl BestScores {
+    /// Where the table is kept unless told otherwise: in the home directory if there is one.
+    pub fn default_path() -> PathBuf {
+        let dir = std::env::var_os("HOME").map_or_else(PathBuf::new, PathBuf::from);
+        dir.join(".battleships_practice.json")
+    }
+
+    /// Loads the table, starting a new one if the file doesn't exist yet.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Ok(serde_json::from_str(&text)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Adds a finished game to the table, returning its 1-based place if it made the table.
+    pub fn record(&mut self, score: BestScore) -> Option<usize> {
+        // ties go below the older score, it got there first
+        let index = self.scores.partition_point(|s| s.shots <= score.shots);
+        if index >= BEST_SCORES_LEN {
+            return None;
+        }
+        self.scores.insert(index, score);
+        self.scores.truncate(BEST_SCORES_LEN);
+        Some(index + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_fleet() {
+        assert_eq!(
+            Practice::with_seed(10, 42).get_grid(),
+            Practice::with_seed(10, 42).get_grid()
+        );
+        assert_eq!(
+            Practice::with_seed(10, 42).get_grid().ships.len(),
+            SHIPS_ORDER.len()
+        );
+    }
+
+    #[test]
+    fn test_fire_until_cleared() {
+        let mut practice = Practice::with_seed(10, 3);
+        assert!(matches!(
+            practice.fire(Location::new(10, 0)),
+            Err(PracticeError::OutOfBounds)
+        ));
+
+        let mut sunk = 0;
+        for y in 0..10 {
+            for x in 0..10 {
+                if practice.is_cleared() {
+                    break;
+                }
+                if let ShotOutcome::Sunk(_) = practice.fire(Location::new(x, y)).unwrap() {
+                    sunk += 1;
+                }
+            }
+        }
+        assert!(practice.is_cleared());
+        assert_eq!(sunk, SHIPS_ORDER.len());
+        assert!(practice.shots() >= 17);
+        assert!(matches!(
+            practice.fire(Location::new(9, 9)),
+            Err(PracticeError::Cleared)
+        ));
+    }
+
+    #[test]
+    fn test_repeated_shot_not_counted() {
+        let mut practice = Practice::with_seed(10, 3);
+        practice.fire(Location::new(0, 0)).unwrap();
+        assert!(matches!(
+            practice.fire(Location::new(0, 0)),
+            Err(PracticeError::AlreadyShot)
+        ));
+        assert_eq!(practice.shots(), 1);
+    }
+
+    #[test]
+    fn test_best_scores_record() {
+        let mut bests = BestScores::default();
+        for shots in [50, 30, 40] {
+            bests.record(BestScore { shots, seed: 0 });
+        }
+        assert_eq!(bests.record(BestScore { shots: 40, seed: 1 }), Some(3));
+        let shots: Vec<u32> = bests.scores.iter().map(|s| s.shots).collect();
+        assert_eq!(shots, vec![30, 40, 40, 50]);
+        assert_eq!(bests.scores[1].seed, 0);
+
+        for _ in 0..10 {
+            bests.record(BestScore { shots: 20, seed: 2 });
+        }
+        assert_eq!(bests.scores.len(), BEST_SCORES_LEN);
+        assert_eq!(bests.record(BestScore { shots: 25, seed: 3 }), None);
+    }
+
+    #[test]
+    fn test_best_scores_save_load() {
+        // a name of its own, so that test runs going at the same time don't share the file
+        let path = std::env::temp_dir().join(format!(
+            "battleships_practice_test_{}_{:016x}.json",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        assert_eq!(BestScores::load(&path).unwrap(), BestScores::default());
+
+        let mut bests = BestScores::default();
+        bests.record(BestScore { shots: 33, seed: 9 });
+        bests.save(&path).unwrap();
+        assert_eq!(BestScores::load(&path).unwrap(), bests);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
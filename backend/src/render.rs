@@ -0,0 +1,311 @@
+//! Drawing the boards in the terminal for each turn: the player's own board next to what they
+//! know of their opponent's, with colours when the terminal can show them.
+
+use std::io::IsTerminal;
+
+use crate::{
+    game::{Game, Player},
+    location::{rect, row_label, Location, Topology},
+    ship::Grid,
+    view::PlayerView,
+};
+
+/// What's drawn in one cell.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cell {
+    Water,
+    /// One of the player's own ships that hasn't been hit there.
+    Ship,
+    /// One of the player's own decoys.
+    Decoy,
+    Miss,
+    /// Hit, but the armour held.
+    Dented,
+    Hit,
+    Sunk,
+}
+
+impl Cell {
+    fn marker(&self) -> char {
+        match self {
+            Cell::Water => '.',
+            Cell::Ship => 'o',
+            Cell::Decoy => 'd',
+            Cell::Miss => '-',
+            Cell::Dented => '+',
+            Cell::Hit => 'x',
+            Cell::Sunk => '#',
+        }
+    }
+
+    /// The ANSI colour code the marker is drawn in.
+    fn colour(&self) -> &'static str {
+        match self {
+            Cell::Water => "34",
+            Cell::Ship | Cell::Decoy => "1",
+            Cell::Miss => "36",
+            Cell::Dented => "33",
+            Cell::Hit => "31",
+            Cell::Sunk => "1;35",
+        }
+    }
+
+    /// How a cell of the player's own grid looks to them.
+    pub fn own(grid: &Grid, loc: Location) -> Cell {
+        match grid.ships.iter().find(|s| s.get_coords().contains(&loc)) {
+            Some(ship) if ship.is_sunk() => Cell::Sunk,
+            Some(ship) if ship.is_found(loc) => Cell::Hit,
+            Some(ship) if ship.is_dented(loc) => Cell::Dented,
+            Some(ship) if ship.is_decoy() => Cell::Decoy,
+            Some(_) => Cell::Ship,
+            None if grid.wrong_guesses.contains(&loc) => Cell::Miss,
+            None => Cell::Water,
+        }
+    }
+
+    /// How a cell of the opponent's grid looks, going only by what the player has been told.
+    pub fn target(view: &PlayerView, loc: Location) -> Cell {
+        if view.is_sunk_at(loc) {
+            Cell::Sunk
+        } else if view.hits.contains(&loc) {
+            Cell::Hit
+        } else if view.dented.contains(&loc) {
+            Cell::Dented
+        } else if view.misses.contains(&loc) {
+            Cell::Miss
+        } else {
+            Cell::Water
+        }
+    }
+}
+
+/// One board to draw, a cell at a time.
+pub struct Board<'a> {
+    pub title: String,
+    pub size: i32,
+    pub depths: i32,
+    pub topology: Topology,
+    pub cell: Box<dyn Fn(Location) -> Cell + 'a>,
+    /// The cell that was shot at most recently, which gets highlighted.
+    pub last_shot: Option<Location>,
+}
+
+impl<'a> Board<'a> {
+    pub fn own(grid: &'a Grid, last_shot: Option<Location>) -> Self {
+        Self {
+            title: "Your fleet".to_string(),
+            size: grid.size,
+            depths: grid.depths,
+            topology: grid.topology,
+            cell: Box::new(move |loc| Cell::own(grid, loc)),
+            last_shot,
+        }
+    }
+
+    pub fn target(view: &'a PlayerView, last_shot: Option<Location>) -> Self {
+        Self {
+            title: "Enemy waters".to_string(),
+            size: view.size,
+            depths: view.depths,
+            topology: view.topology,
+            cell: Box::new(move |loc| Cell::target(view, loc)),
+            last_shot,
+        }
+    }
+}
+
+/// A line of output along with how wide it looks, which isn't its length once colours are in it.
+struct Line {
+    text: String,
+    width: usize,
+}
+
+impl Line {
+    fn push(&mut self, s: &str, width: usize) {
+        self.text.push_str(s);
+        self.width += width;
+    }
+}
+
+/// Puts `columns` of lines next to each other, lined up at the top with `gap` spaces between.
+fn beside(columns: Vec<Vec<Line>>, gap: usize) -> Vec<Line> {
+    let widths: Vec<usize> = (columns.iter())
+        .map(|lines| lines.iter().map(|l| l.width).max().unwrap_or(0))
+        .collect();
+    let rows = columns.iter().map(|lines| lines.len()).max().unwrap_or(0);
+
+    let mut out = vec![];
+    for row in 0..rows {
+        let mut joined = Line {
+            text: String::new(),
+            width: 0,
+        };
+        for (i, lines) in columns.iter().enumerate() {
+            let line = lines.get(row);
+            let width = line.map_or(0, |l| l.width);
+            joined.push(line.map_or("", |l| &l.text), width);
+            if i + 1 < columns.len() {
+                let pad = widths[i] - width + gap;
+                joined.push(&" ".repeat(pad), pad);
+            }
+        }
+        out.push(joined);
+    }
+    out
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Renderer {
+    pub colour: bool,
+}
+
+impl Renderer {
+    /// Uses colours unless output isn't going to a terminal or `NO_COLOR` is set.
+    pub fn detect() -> Self {
+        let colour = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+        Self { colour }
+    }
+
+    pub fn plain() -> Self {
+        Self { colour: false }
+    }
+
+    /// Both boards for `player`'s turn: their own on the left and the opponent's on the right.
+    pub fn turn(&self, game: &Game, player: Player) -> String {
+        let view = game.view(player);
+        let own = Board::own(game.get_grid(player), game.last_shot(player.other()));
+        let target = Board::target(&view, game.last_shot(player));
+        let boards = [own, target];
+        self.side_by_side(&boards)
+    }
+
+    /// Draws `boards` next to each other, lined up at the top.
+    pub fn side_by_side(&self, boards: &[Board]) -> String {
+        let drawn = boards.iter().map(|b| self.lines(b)).collect();
+        let mut out = String::new();
+        for line in beside(drawn, 4) {
+            out.push_str(line.text.trim_end());
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Every layer of one board, next to each other like `GridDisplay` draws them.
+    fn lines(&self, board: &Board) -> Vec<Line> {
+        let layers = (0..=board.depths)
+            .map(|layer| self.layer_lines(board, layer))
+            .collect();
+        beside(layers, 2)
+    }
+
+    /// One layer of a board. Each cell is three characters wide so that two-digit column numbers
+    /// line up and the last shot can be bracketed without colours.
+    fn layer_lines(&self, board: &Board, layer: i32) -> Vec<Line> {
+        let label_width = row_label(board.size - 1).len();
+        let mut lines = vec![];
+        let title = match layer {
+            _ if board.depths == 0 => board.title.clone(),
+            0 => format!("{} (surface)", board.title),
+            _ => format!("{} (depth {layer})", board.title),
+        };
+        let width = title.chars().count();
+        lines.push(Line { text: title, width });
+
+        let mut header = Line {
+            text: String::new(),
+            width: 0,
+        };
+        header.push(&" ".repeat(label_width + 1), label_width + 1);
+        for x in 1..board.size + 1 {
+            header.push(&format!("{x:^3}"), 3);
+        }
+        lines.push(header);
+
+        for y in 0..board.size {
+            let mut line = Line {
+                text: String::new(),
+                width: 0,
+            };
+            line.push(&format!("{:label_width$} ", row_label(y)), label_width + 1);
+            if board.topology == Topology::Hex {
+                // each row of hexes sits half a hex to the right of the one above
+                let indent = y as usize * 3 / 2;
+                line.push(&" ".repeat(indent), indent);
+            }
+            for cur in rect(Location::at_depth(0, y, layer), board.size, 1) {
+                let cell = (board.cell)(cur);
+                line.push(&self.cell(cell, board.last_shot == Some(cur)), 3);
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    fn cell(&self, cell: Cell, last_shot: bool) -> String {
+        let marker = cell.marker();
+        match (self.colour, last_shot) {
+            (false, false) => format!(" {marker} "),
+            (false, true) => format!("[{marker}]"),
+            // reverse video makes the last shot stand out whatever colour it is
+            (true, false) => format!("\x1b[{}m {marker} \x1b[0m", cell.colour()),
+            (true, true) => format!("\x1b[{};7m {marker} \x1b[0m", cell.colour()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{game::test_game, ship::ShipClass};
+
+    #[test]
+    fn test_turn_plain() {
+        let game = test_game(
+            &[ShipClass::Destroyer],
+            ["AA.\n...\n...", "...\n...\nAA."],
+            &["C1", "C3"],
+        );
+
+        assert_eq!(
+            Renderer::plain().turn(&game, Player::Player1),
+            "\
+Your fleet     Enemy waters
+   1  2  3        1  2  3
+A  o  o  .     A  .  .  .
+B  .  .  .     B  .  .  .
+C  .  . [-]    C [x] .  .
+"
+        );
+    }
+
+    #[test]
+    fn test_two_digit_columns_and_colour() {
+        let grid = Grid::new(12);
+        let board = Board::own(&grid, Some(Location::new(11, 0)));
+        let out = Renderer::plain().side_by_side(&[board]);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[1], "   1  2  3  4  5  6  7  8  9 10 11 12");
+        // the last column is right under its number
+        assert_eq!(lines[2].find("[.]"), lines[1].find("12"));
+
+        let board = Board::own(&grid, Some(Location::new(0, 0)));
+        let out = Renderer { colour: true }.side_by_side(&[board]);
+        assert!(out.contains("\x1b[34;7m . \x1b[0m"));
+        assert!(out.contains("\x1b[34m . \x1b[0m"));
+    }
+
+    #[test]
+    fn test_layers_side_by_side() {
+        let grid = Grid::new(2).with_depths(1);
+        let board = Board::own(&grid, None);
+        assert_eq!(
+            Renderer::plain().side_by_side(&[board]),
+            "\
+Your fleet (surface)  Your fleet (depth 1)
+   1  2                  1  2
+A  .  .               A  .  .
+B  .  .               B  .  .
+"
+        );
+    }
+}
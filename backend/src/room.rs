@@ -0,0 +1,261 @@
+//! Private rooms, so that two particular people can play each other. One of them creates a room
+//! with the rules they want and passes its code on, then both join with the code. A room is good
+//! for one game and goes away after a while whether or not it was used.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+use serde::Serialize;
+use thiserror::Error;
+use tokio::sync::oneshot;
+
+use crate::game::{Rules, RulesError};
+
+/// How long a room lasts after it's created.
+pub const ROOM_TTL: Duration = Duration::from_secs(10 * 60);
+
+const CODE_LEN: usize = 6;
+/// Letters and digits that can't be mistaken for each other when read out.
+const CODE_CHARS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RoomError {
+    #[error("there is no room with that code")]
+    NotFound,
+    #[error("that room's game has already started")]
+    Started,
+    #[error(transparent)]
+    BadRules(#[from] RulesError),
+}
+
+/// What can be told about a room to anyone with its code.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RoomInfo {
+    pub code: String,
+    pub rules: Rules,
+    #[serde(serialize_with = "crate::game::serialize_seed")]
+    pub seed: u64,
+    /// How many players have joined so far.
+    pub players: usize,
+    /// Seconds until the room goes away.
+    pub expires_in: u64,
+}
+
+/// How a player got into a room.
+#[derive(Debug)]
+pub enum Joined<P> {
+    /// They're the first one in and their opponent will come through here. If the room expires
+    /// first, the sender is dropped instead.
+    First(oneshot::Receiver<P>),
+    /// Their opponent is already waiting and they should hand themselves over through here.
+    Second(oneshot::Sender<P>),
+}
+
+struct Room<P> {
+    rules: Rules,
+    seed: u64,
+    created: Instant,
+    waiting: Option<oneshot::Sender<P>>,
+    started: bool,
+}
+
+impl<P> Room<P> {
+    /// Whether someone is waiting in the room and hasn't left.
+    fn has_waiting(&self) -> bool {
+        self.waiting.as_ref().is_some_and(|w| !w.is_closed())
+    }
+}
+
+/// Every room on the server, where `P` is however a player is connected.
+pub struct Rooms<P> {
+    rooms: Mutex<HashMap<String, Room<P>>>,
+    ttl: Duration,
+}
+
+impl<P> Default for Rooms<P> {
+    fn default() -> Self {
+        Self::with_ttl(ROOM_TTL)
+    }
+}
+
+impl<P> Rooms<P> {
+    pub fn with_ttl(ttl: Duration) -> Self {
+        Self {
+            rooms: Mutex::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    pub fn create(&self, rules: Rules) -> Result<RoomInfo, RoomError> {
+        rules.check()?;
+
+        let mut rooms = self.rooms.lock().unwrap();
+        let mut rng = rand::thread_rng();
+        let code = loop {
+            let code: String = (0..CODE_LEN)
+                .map(|_| char::from(CODE_CHARS[rng.gen_range(0..CODE_CHARS.len())]))
+                .collect();
+            if !rooms.contains_key(&code) {
+                break code;
+            }
+        };
+        let room = Room {
+            rules,
+            seed: rng.gen(),
+            created: Instant::now(),
+            waiting: None,
+            started: false,
+        };
+        let info = self.info_of(&code, &room);
+        rooms.insert(code, room);
+        Ok(info)
+    }
+
+    /// Codes can be typed in either case.
+    pub fn info(&self, code: &str) -> Result<RoomInfo, RoomError> {
+        let code = code.to_uppercase();
+        let rooms = self.rooms.lock().unwrap();
+        match rooms.get(&code) {
+            Some(room) if !self.expired(room) => Ok(self.info_of(&code, room)),
+            _ => Err(RoomError::NotFound),
+        }
+    }
+
+    pub fn join(&self, code: &str) -> Result<(RoomInfo, Joined<P>), RoomError> {
+        let code = code.to_uppercase();
+        let mut rooms = self.rooms.lock().unwrap();
+        let room = match rooms.get_mut(&code) {
+            Some(room) if !self.expired(room) => room,
+            _ => return Err(RoomError::NotFound),
+        };
+        if room.started {
+            return Err(RoomError::Started);
+        }
+
+        let joined = if room.has_waiting() {
+            room.started = true;
+            Joined::Second(room.waiting.take().unwrap())
+        } else {
+            // if the player who was waiting has left, the room is free again
+            let (sender, receiver) = oneshot::channel();
+            room.waiting = Some(sender);
+            Joined::First(receiver)
+        };
+        Ok((self.info_of(&code, room), joined))
+    }
+
+    /// Gets rid of the rooms that have expired, which also tells anyone still waiting in them.
+    pub fn sweep(&self) {
+        let mut rooms = self.rooms.lock().unwrap();
+        rooms.retain(|_, room| !self.expired(room));
+    }
+
+    fn expired(&self, room: &Room<P>) -> bool {
+        room.created.elapsed() >= self.ttl
+    }
+
+    fn info_of(&self, code: &str, room: &Room<P>) -> RoomInfo {
+        let players = match room {
+            _ if room.started => 2,
+            _ if room.has_waiting() => 1,
+            _ => 0,
+        };
+        RoomInfo {
+            code: code.to_string(),
+            rules: room.rules.clone(),
+            seed: room.seed,
+            players,
+            expires_in: self.ttl.saturating_sub(room.created.elapsed()).as_secs(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join() {
+        let rooms = Rooms::<&str>::default();
+        let info = rooms.create(Rules::standard(8)).unwrap();
+        assert_eq!(info.code.len(), CODE_LEN);
+        assert_eq!(info.players, 0);
+        assert!(info.expires_in > 0);
+
+        let code = info.code.to_lowercase();
+        let (_, Joined::First(mut opponent)) = rooms.join(&code).unwrap() else {
+            panic!("the first player in should wait");
+        };
+        assert_eq!(rooms.info(&code).unwrap().players, 1);
+        let (info, Joined::Second(sender)) = rooms.join(&code).unwrap() else {
+            panic!("the second player in should be paired");
+        };
+        assert_eq!(info.rules, Rules::standard(8));
+        assert_eq!(info.players, 2);
+        sender.send("second").unwrap();
+        assert_eq!(opponent.try_recv(), Ok("second"));
+
+        assert_eq!(rooms.join(&code).unwrap_err(), RoomError::Started);
+        assert_eq!(rooms.join("NOPE").unwrap_err(), RoomError::NotFound);
+    }
+
+    #[test]
+    fn test_waiting_player_leaving() {
+        let rooms = Rooms::<&str>::default();
+        let code = rooms.create(Rules::standard(8)).unwrap().code;
+        let (_, first) = rooms.join(&code).unwrap();
+        drop(first);
+        // the next player in takes their place rather than being paired with nobody
+        assert!(matches!(rooms.join(&code).unwrap().1, Joined::First(_)));
+    }
+
+    #[test]
+    fn test_expiry() {
+        let rooms = Rooms::<&str>::with_ttl(Duration::ZERO);
+        let code = rooms.create(Rules::standard(8)).unwrap().code;
+        assert_eq!(rooms.info(&code).unwrap_err(), RoomError::NotFound);
+        assert_eq!(rooms.join(&code).unwrap_err(), RoomError::NotFound);
+
+        let rooms = Rooms::<&str>::with_ttl(Duration::from_millis(50));
+        let code = rooms.create(Rules::standard(8)).unwrap().code;
+        let (_, Joined::First(mut opponent)) = rooms.join(&code).unwrap() else {
+            panic!("the first player in should wait");
+        };
+        std::thread::sleep(Duration::from_millis(60));
+        rooms.sweep();
+        // whoever was waiting finds out the room has gone
+        assert_eq!(
+            opponent.try_recv(),
+            Err(oneshot::error::TryRecvError::Closed)
+        );
+    }
+
+    #[test]
+    fn test_bad_rules() {
+        let rooms = Rooms::<&str>::default();
+        assert_eq!(
+            rooms.create(Rules::standard(0)),
+            Err(RoomError::BadRules(RulesError::BadSize))
+        );
+        assert_eq!(
+            rooms.create(Rules::standard(27)),
+            Err(RoomError::BadRules(RulesError::BadSize))
+        );
+        let mut rules = Rules::standard(8);
+        rules.depths = 4;
+        assert_eq!(
+            rooms.create(rules),
+            Err(RoomError::BadRules(RulesError::TooDeep))
+        );
+        let mut rules = Rules::standard(8);
+        rules.players[0].fleet = vec![];
+        assert_eq!(
+            rooms.create(rules),
+            Err(RoomError::BadRules(RulesError::EmptyFleet(1)))
+        );
+    }
+}
@@ -0,0 +1,67 @@
+//! Session tokens, which let a player whose connection dropped get back into their game.
+
+use std::{collections::HashMap, sync::Mutex};
+
+/// A new token that can't be guessed.
+pub fn new_token() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+/// Whatever is needed to get back into a game, by the token of the player it's for.
+pub struct Sessions<T> {
+    sessions: Mutex<HashMap<String, T>>,
+}
+
+impl<T> Default for Sessions<T> {
+    fn default() -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T: Clone> Sessions<T> {
+    pub fn insert(&self, token: String, session: T) {
+        self.sessions.lock().unwrap().insert(token, session);
+    }
+
+    pub fn get(&self, token: &str) -> Option<T> {
+        self.sessions.lock().unwrap().get(token).cloned()
+    }
+
+    /// For when the game is over and there's nothing left to get back into.
+    pub fn remove(&self, token: &str) {
+        self.sessions.lock().unwrap().remove(token);
+    }
+
+    /// Keeps only the sessions that `keep` is true for.
+    pub fn retain(&self, mut keep: impl FnMut(&T) -> bool) {
+        self.sessions
+            .lock()
+            .unwrap()
+            .retain(|_, session| keep(session));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sessions() {
+        let first = new_token();
+        let second = new_token();
+        assert_eq!(first.len(), 32);
+        assert_ne!(first, second);
+
+        let sessions = Sessions::default();
+        sessions.insert(first.clone(), 1);
+        sessions.insert(second.clone(), 2);
+        assert_eq!(sessions.get(&first), Some(1));
+        sessions.remove(&first);
+        assert_eq!(sessions.get(&first), None);
+        assert_eq!(sessions.get(&second), Some(2));
+        sessions.retain(|&n| n != 2);
+        assert_eq!(sessions.get(&second), None);
+    }
+}
@@ -1,38 +1,176 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    str::FromStr,
+};
 
+use rand::Rng;
 use thiserror::Error;
 
-use crate::location::{self, Location};
+use crate::{
+    hex::{HexCoord, HexDir},
+    location::{rect, row_label, Dir, Location, Topology},
+};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Ship {
     coords: Vec<Location>,
-    found: HashMap<Location, bool>,
+    /// How many more hits each cell can take before it's destroyed.
+    hit_points: HashMap<Location, u32>,
+    /// How many hits each cell could take to begin with.
+    armour: u32,
+    /// Decoys look like ships when they're hit, but don't have to be sunk to win.
+    decoy: bool,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 pub enum ShipDir {
     Horz,
     Vert,
 }
 
+impl From<ShipDir> for Dir {
+    /// Ships go right or towards bigger `y` from where they start.
+    fn from(dir: ShipDir) -> Self {
+        match dir {
+            ShipDir::Horz => Dir::Right,
+            ShipDir::Vert => Dir::Up,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+pub enum ShipClass {
+    Destroyer,
+    Submarine,
+    Cruiser,
+    Battleship,
+    Carrier,
+}
+
+impl ShipClass {
+    pub fn size(&self) -> i32 {
+        match self {
+            ShipClass::Destroyer => 2,
+            ShipClass::Submarine => 3,
+            ShipClass::Cruiser => 3,
+            ShipClass::Battleship => 4,
+            ShipClass::Carrier => 5,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ShipClass::Destroyer => "Destroyer",
+            ShipClass::Submarine => "Submarine",
+            ShipClass::Cruiser => "Cruiser",
+            ShipClass::Battleship => "Battleship",
+            ShipClass::Carrier => "Carrier",
+        }
+    }
+
+    /// Whether the ship sails below the surface on boards with depth layers.
+    pub fn submerged(&self) -> bool {
+        *self == ShipClass::Submarine
+    }
+
+    /// The layers a ship of this class can be put on, on a board with `depths` layers under the
+    /// surface.
+    pub fn layers(&self, depths: i32) -> std::ops::RangeInclusive<i32> {
+        if depths > 0 && self.submerged() {
+            1..=depths
+        } else {
+            0..=0
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ShipClass> {
+        [
+            ShipClass::Destroyer,
+            ShipClass::Submarine,
+            ShipClass::Cruiser,
+            ShipClass::Battleship,
+            ShipClass::Carrier,
+        ]
+        .into_iter()
+        .find(|c| c.name().eq_ignore_ascii_case(name))
+    }
+}
+
+impl std::fmt::Display for ShipClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
 impl Ship {
-    pub fn new(start: Location, dir: ShipDir, len: i32) -> Self {
-        let mut coords = vec![];
-        for o in 0..len {
-            let new = match dir {
-                ShipDir::Horz => Location::new(start.x + o, start.y),
-                ShipDir::Vert => Location::new(start.x, start.y + o),
-            };
-            coords.push(new);
+    /// A ship of length `len` going from `start` in `dir`. `ShipDir` still works for ships that go
+    /// right or towards bigger `y`.
+    pub fn new(start: Location, dir: impl Into<Dir>, len: i32) -> Self {
+        Self::from_coords(start.line(dir.into(), len).collect())
+    }
+
+    /// Like `new`, but for a board of `size` with the given topology, so on a torus the ship
+    /// wraps around the edges instead of running off them. Ships that start off the board are
+    /// left alone so they still count as out of bounds.
+    pub fn new_on(
+        start: Location,
+        dir: impl Into<Dir>,
+        len: i32,
+        topology: Topology,
+        size: i32,
+    ) -> Self {
+        let ship = Self::new(start, dir, len);
+        if topology.wrap(start, size) != start {
+            return ship;
+        }
+        let coords = ship.coords.iter().map(|&c| topology.wrap(c, size));
+        Self::from_coords(coords.collect())
+    }
+
+    /// A ship on a hex board, going from `start` in any of the six directions.
+    pub fn new_hex(start: HexCoord, dir: HexDir, len: i32) -> Self {
+        Self::from_coords(
+            start
+                .line(dir, len)
+                .into_iter()
+                .map(Location::from)
+                .collect(),
+        )
+    }
+
+    /// The same ship moved to a different layer.
+    pub fn on_layer(&self, layer: i32) -> Self {
+        Self::from_coords(self.coords.iter().map(|c| c.with_layer(layer)).collect())
+    }
+
+    fn from_coords(coords: Vec<Location>) -> Self {
+        let hit_points = coords.iter().map(|&c| (c, 1)).collect();
+        Self {
+            coords,
+            hit_points,
+            armour: 1,
+            decoy: false,
         }
+    }
 
-        let mut found = HashMap::new();
-        for &c in &coords {
-            found.insert(c, false);
+    /// Gives every cell of the ship `armour` hit points, so it takes that many hits to destroy.
+    pub fn set_armour(&mut self, armour: u32) {
+        self.armour = armour.max(1);
+        for hp in self.hit_points.values_mut() {
+            *hp = self.armour;
         }
+    }
 
-        Self { coords, found }
+    pub fn armour(&self) -> u32 {
+        self.armour
+    }
+
+    pub fn set_decoy(&mut self, decoy: bool) {
+        self.decoy = decoy;
+    }
+
+    pub fn is_decoy(&self) -> bool {
+        self.decoy
     }
 
     // &[T] is an "immutable slice", like a borrowed version of a Vec but you can't mutate it at all
@@ -48,13 +186,49 @@ impl Ship {
     }
 
     pub fn guess(&mut self, coord: Location) -> bool {
-        if self.coords.contains(&coord) {
-            self.found.insert(coord, true);
-            true
-        } else {
-            false
+        match self.hit_points.get_mut(&coord) {
+            Some(hp) => {
+                *hp = hp.saturating_sub(1);
+                true
+            }
+            None => false,
         }
     }
+
+    /// Undoes the damage to one cell, as long as the ship hasn't been sunk. Returns whether there
+    /// was anything to repair.
+    pub fn repair(&mut self, coord: Location) -> bool {
+        if self.is_sunk() {
+            return false;
+        }
+        match self.hit_points.get_mut(&coord) {
+            Some(hp) if *hp < self.armour => {
+                *hp = self.armour;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// How many more hits the cell can take, or `None` if it isn't part of the ship.
+    pub fn hit_points(&self, coord: Location) -> Option<u32> {
+        self.hit_points.get(&coord).copied()
+    }
+
+    /// Whether the cell has been destroyed.
+    pub fn is_found(&self, coord: Location) -> bool {
+        self.hit_points(coord) == Some(0)
+    }
+
+    /// Whether the cell has been hit but its armour is still holding.
+    pub fn is_dented(&self, coord: Location) -> bool {
+        self.hit_points(coord)
+            .is_some_and(|hp| hp > 0 && hp < self.armour)
+    }
+
+    pub fn is_sunk(&self) -> bool {
+        self.coords.iter().all(|&c| self.is_found(c))
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
@@ -63,28 +237,76 @@ pub enum AddShipError {
     ShipOutOfBounds,
     #[error("ship overlaps an existing ship")]
     ShipOverlap,
+    #[error("ship is too long to fit around the board")]
+    ShipTooLong,
+    #[error("there is no room left for a ship of this length")]
+    NoRoom,
 }
 
+/// Why a whole layout couldn't be placed: each ship that didn't fit, by its index in the layout.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{} of the ships could not be placed", .0.len())]
+pub struct PlaceFleetError(pub Vec<(usize, AddShipError)>);
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Grid {
     pub ships: Vec<Ship>,
     pub wrong_guesses: Vec<Location>,
     pub size: i32,
+    pub topology: Topology,
+    /// How many layers there are below the surface.
+    pub depths: i32,
 }
 impl Grid {
     pub fn new(size: i32) -> Self {
+        Self::with_topology(size, Topology::Flat)
+    }
+
+    pub fn with_topology(size: i32, topology: Topology) -> Self {
         Self {
             ships: vec![],
             size,
             wrong_guesses: vec![],
+            topology,
+            depths: 0,
         }
     }
 
+    /// Gives the grid `depths` layers below the surface.
+    pub fn with_depths(mut self, depths: i32) -> Self {
+        self.depths = depths;
+        self
+    }
+
+    /// Makes a ship that follows this grid's topology. It still needs adding with `add_ship`.
+    pub fn new_ship(&self, start: Location, dir: impl Into<Dir>, len: i32) -> Ship {
+        Ship::new_on(start, dir, len, self.topology, self.size)
+    }
+
     pub fn add_ship(&mut self, ship: Ship) -> Result<(), AddShipError> {
-        let coord_oob = |coord: Location| {
-            coord.x >= self.size || coord.x < 0 || coord.y >= self.size || coord.y < 0
-        };
+        self.check_ship(&ship)?;
+        self.ships.push(ship);
+        Ok(())
+    }
 
+    /// Adds every ship in `layout`, or none of them if any of them can't go where they are.
+    pub fn place_fleet(&mut self, layout: Vec<Ship>) -> Result<(), PlaceFleetError> {
+        let mut grid = self.clone();
+        let mut errors = vec![];
+        for (i, ship) in layout.into_iter().enumerate() {
+            if let Err(e) = grid.add_ship(ship) {
+                errors.push((i, e));
+            }
+        }
+        if !errors.is_empty() {
+            return Err(PlaceFleetError(errors));
+        }
+        *self = grid;
+        Ok(())
+    }
+
+    /// Checks whether `ship` could be added to the grid without actually adding it.
+    pub fn check_ship(&self, ship: &Ship) -> Result<(), AddShipError> {
         let existing_coords: HashSet<Location> = self
             .ships
             .iter()
@@ -94,17 +316,54 @@ impl Grid {
 
         let new_coords: HashSet<Location> = ship.coords.iter().copied().collect();
 
+        // only possible when a ship wraps all the way round and runs into itself
+        if new_coords.len() < ship.coords.len() {
+            return Err(AddShipError::ShipTooLong);
+        }
+
         if !existing_coords.is_disjoint(&new_coords) {
             return Err(AddShipError::ShipOverlap);
         }
 
-        for c in &ship.coords {
-            if coord_oob(*c) {
-                return Err(AddShipError::ShipOutOfBounds);
-            }
+        if !ship.coords.iter().all(|&c| self.contains(c)) {
+            return Err(AddShipError::ShipOutOfBounds);
         }
 
-        self.ships.push(ship);
+        Ok(())
+    }
+
+    /// Whether `coord` is on the board, on one of its layers.
+    pub fn contains(&self, coord: Location) -> bool {
+        (0..self.size).contains(&coord.x)
+            && (0..self.size).contains(&coord.y)
+            && (0..=self.depths).contains(&coord.layer)
+    }
+
+    /// Adds a ship of length `len` at a position chosen uniformly from every valid placement.
+    pub fn add_random_ship(&mut self, len: i32, rng: &mut impl Rng) -> Result<(), AddShipError> {
+        self.add_random_ship_on(len, 0..=0, rng)
+    }
+
+    /// Like `add_random_ship`, but the ship can go on any of `layers`.
+    pub fn add_random_ship_on(
+        &mut self,
+        len: i32,
+        layers: std::ops::RangeInclusive<i32>,
+        rng: &mut impl Rng,
+    ) -> Result<(), AddShipError> {
+        let mut candidates: Vec<Ship> = layers
+            .flat_map(|layer| {
+                let ships = placements(self.size, self.topology, len);
+                ships.into_iter().map(move |s| s.on_layer(layer))
+            })
+            .filter(|s| self.check_ship(s).is_ok())
+            .collect();
+
+        if candidates.is_empty() {
+            return Err(AddShipError::NoRoom);
+        }
+        let index = rng.gen_range(0..candidates.len());
+        self.ships.push(candidates.swap_remove(index));
         Ok(())
     }
 
@@ -118,11 +377,12 @@ impl Grid {
         x
     }
 
+    /// Every ship cell that has been destroyed.
     pub fn get_all_found(&self) -> Vec<Location> {
         let mut x = vec![];
         for s in &self.ships {
-            for (&l, &b) in &s.found {
-                if b {
+            for (&l, &hp) in &s.hit_points {
+                if hp == 0 {
                     x.push(l);
                 }
             }
@@ -130,21 +390,42 @@ impl Grid {
         x
     }
 
+    /// Every ship cell that has been hit without being destroyed, because of its armour.
+    pub fn get_all_dented(&self) -> Vec<Location> {
+        let mut x = vec![];
+        for s in &self.ships {
+            for &l in &s.coords {
+                if s.is_dented(l) {
+                    x.push(l);
+                }
+            }
+        }
+        x
+    }
+
+    /// Whether every cell of every ship has been destroyed. Cells with armour left don't count,
+    /// and decoys don't need sinking.
     pub fn check_loss(&self) -> bool {
-        self.get_all().len() == self.get_all_found().len()
+        self.ships.iter().all(|s| s.is_decoy() || s.is_sunk())
+    }
+
+    /// Repairs a damaged cell of a ship that is still afloat, returning whether it worked.
+    pub fn repair(&mut self, coord: Location) -> bool {
+        self.ships.iter_mut().any(|s| s.repair(coord))
     }
 
-    pub fn guess_grid(&mut self, coords: Location) -> bool {
+    /// Returns the index of the ship that was hit, if any.
+    pub fn guess_grid(&mut self, coords: Location) -> Option<usize> {
         for i in 0..self.ships.len() {
             if self.ships[i].guess(coords) {
-                return true;
+                return Some(i);
             }
         }
         self.wrong_guesses.push(coords);
-        false
+        None
     }
 
-    pub fn get_display(&self, see_unfound: bool) -> GridDisplay {
+    pub fn get_display(&self, see_unfound: bool) -> GridDisplay<'_> {
         GridDisplay {
             grid: self,
             see_unfound,
@@ -152,31 +433,207 @@ impl Grid {
     }
 }
 
+/// The letter each ship gets in a layout picture, by its index in the grid.
+const SHIP_LETTERS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ParseGridError {
+    #[error("the layout is empty")]
+    Empty,
+    #[error("row {0} isn't the same length as the number of rows")]
+    NotSquare(usize),
+    #[error("layer {0} doesn't have as many rows as the surface")]
+    LayerSize(usize),
+    #[error("'{0}' isn't a ship letter or '.'")]
+    UnknownCell(char),
+    #[error("the cells marked '{0}' aren't in one straight line")]
+    NotStraight(char),
+    #[error("ship '{0}' can't go there: {1}")]
+    Ship(char, AddShipError),
+}
+
+/// A compact picture of where the ships are, for saving layouts: one character per cell, with
+/// `.` for water and a letter for each ship in the order they were added. Layers below the
+/// surface come after the surface, separated by blank lines. Shots aren't shown.
+impl std::fmt::Display for Grid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for layer in 0..=self.depths {
+            if layer > 0 {
+                writeln!(f)?;
+            }
+            for y in 0..self.size {
+                for cur in rect(Location::at_depth(0, y, layer), self.size, 1) {
+                    let letter = match self.ships.iter().position(|s| s.coords.contains(&cur)) {
+                        Some(i) => SHIP_LETTERS.chars().nth(i).unwrap_or('?'),
+                        None => '.',
+                    };
+                    write!(f, "{}", letter)?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads a layout in the format written by `Display`. Ships are added in the order of their
+/// letters, and the board is always flat.
+impl FromStr for Grid {
+    type Err = ParseGridError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lines: Vec<&str> = s.trim().lines().map(str::trim).collect();
+        let layers: Vec<&[&str]> = lines.split(|l| l.is_empty()).collect();
+        let size = layers[0].len();
+        if size == 0 {
+            return Err(ParseGridError::Empty);
+        }
+
+        let mut cells: BTreeMap<char, Vec<Location>> = BTreeMap::new();
+        for (layer, rows) in layers.iter().enumerate() {
+            if rows.len() != size {
+                return Err(ParseGridError::LayerSize(layer));
+            }
+            for (y, row) in rows.iter().enumerate() {
+                if row.chars().count() != size {
+                    return Err(ParseGridError::NotSquare(layer * (size + 1) + y + 1));
+                }
+                for (x, c) in row.chars().enumerate() {
+                    match c {
+                        '.' => {}
+                        c if c.is_ascii_alphabetic() => cells
+                            .entry(c)
+                            .or_default()
+                            .push(Location::at_depth(x as i32, y as i32, layer as i32)),
+                        c => return Err(ParseGridError::UnknownCell(c)),
+                    }
+                }
+            }
+        }
+
+        let mut grid = Grid::new(size as i32).with_depths(layers.len() as i32 - 1);
+        for (letter, coords) in cells {
+            // cells are found row by row, so the first one is always the start of the ship
+            let start = coords[0];
+            let len = coords.len() as i32;
+            let ship = [ShipDir::Horz, ShipDir::Vert]
+                .into_iter()
+                .map(|dir| Ship::new(start, dir, len))
+                .find(|ship| ship.coords == coords)
+                .ok_or(ParseGridError::NotStraight(letter))?;
+            grid.add_ship(ship)
+                .map_err(|e| ParseGridError::Ship(letter, e))?;
+        }
+        Ok(grid)
+    }
+}
+
+/// Every way a ship of length `len` can be put on an empty board, each counted once. Ships that
+/// don't fit on the board are left out.
+pub fn placements(size: i32, topology: Topology, len: i32) -> Vec<Ship> {
+    let mut ships = vec![];
+    for start in rect(Location::new(0, 0), size, size) {
+        match topology {
+            Topology::Hex => {
+                for dir in HexDir::AXES {
+                    ships.push(Ship::new_hex(start.into(), dir, len));
+                }
+            }
+            _ => {
+                for dir in [ShipDir::Horz, ShipDir::Vert] {
+                    ships.push(Ship::new_on(start, dir, len, topology, size));
+                }
+            }
+        }
+    }
+    let board = Grid::new(size);
+    ships.retain(|s| {
+        let coords: HashSet<Location> = s.coords.iter().copied().collect();
+        coords.len() == s.coords.len() && coords.iter().all(|&c| board.contains(c))
+    });
+    ships
+}
+
 pub struct GridDisplay<'g> {
     grid: &'g Grid,
     see_unfound: bool,
 }
 
-impl<'g> std::fmt::Display for GridDisplay<'g> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let all = self.grid.get_all();
-        let found = self.grid.get_all_found();
-        write!(f, "  ")?;
+impl<'g> GridDisplay<'g> {
+    /// Draws one layer of the grid, a line at a time.
+    fn layer_lines(&self, layer: i32, all: &[Location], found: &[Location]) -> Vec<String> {
+        let dented = self.grid.get_all_dented();
+        let decoys: Vec<Location> = (self.grid.ships.iter())
+            .filter(|s| s.is_decoy())
+            .flat_map(|s| s.coords.iter().copied())
+            .collect();
+        use std::fmt::Write;
+
+        // big boards have two-letter rows, so leave room for the longest label
+        let label_width = row_label(self.grid.size - 1).len();
+        let mut lines = vec![];
+        let mut line = " ".repeat(label_width + 1);
         for x in 1..self.grid.size + 1 {
-            write!(f, "{x} ")?;
+            write!(line, "{x} ").unwrap();
         }
-        writeln!(f)?;
+        lines.push(line);
 
         for y in 0..self.grid.size {
-            write!(f, "{} ", char::from_u32('A' as u32 + y as u32).unwrap())?;
-            for x in 0..self.grid.size {
-                let cur = Location::new(x, y);
+            let mut line = format!("{:label_width$} ", row_label(y));
+            if self.grid.topology == Topology::Hex {
+                // each row of hexes sits half a hex to the right of the one above
+                write!(line, "{:1$}", "", y as usize).unwrap();
+            }
+            for cur in rect(Location::at_depth(0, y, layer), self.grid.size, 1) {
                 if found.contains(&cur) {
-                    write!(f, "x ")?;
+                    line.push_str("x ");
+                } else if dented.contains(&cur) {
+                    // hit, but the armour held
+                    line.push_str("+ ");
+                } else if self.see_unfound && decoys.contains(&cur) {
+                    line.push_str("d ");
                 } else if self.see_unfound && all.contains(&cur) {
-                    write!(f, "o ")?;
+                    line.push_str("o ");
+                } else {
+                    line.push_str(". ");
+                }
+            }
+            lines.push(line);
+        }
+        lines
+    }
+}
+
+impl<'g> std::fmt::Display for GridDisplay<'g> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let all = self.grid.get_all();
+        let found = self.grid.get_all_found();
+        let layers: Vec<Vec<String>> = (0..=self.grid.depths)
+            .map(|layer| self.layer_lines(layer, &all, &found))
+            .collect();
+        let width = layers[0].iter().map(|l| l.len()).max().unwrap_or(0);
+
+        // layers are drawn side by side, surface first
+        if self.grid.depths > 0 {
+            for layer in 0..=self.grid.depths {
+                let title = match layer {
+                    0 => "Surface".to_string(),
+                    _ => format!("Depth {layer}"),
+                };
+                if layer < self.grid.depths {
+                    write!(f, "{title:width$}  ")?;
                 } else {
-                    write!(f, ". ")?;
+                    write!(f, "{title}")?;
+                }
+            }
+            writeln!(f)?;
+        }
+        for row in 0..layers[0].len() {
+            for (i, lines) in layers.iter().enumerate() {
+                if i + 1 < layers.len() {
+                    write!(f, "{:width$}  ", lines[row])?;
+                } else {
+                    write!(f, "{}", lines[row])?;
                 }
             }
             writeln!(f)?;
@@ -193,70 +650,319 @@ mod tests {
     fn ship_horz_3() -> Ship {
         Ship {
             coords: vec![
-                Location { x: 0, y: 0 },
-                Location { x: 1, y: 0 },
-                Location { x: 2, y: 0 },
+                Location::new(0, 0),
+                Location::new(1, 0),
+                Location::new(2, 0),
             ],
-            found: hashmap! {
-                Location { x: 0, y: 0 } => false,
-                Location { x: 1, y: 0 } => false,
-                Location { x: 2, y: 0 } => false,
+            hit_points: hashmap! {
+                Location::new(0, 0) => 1,
+                Location::new(1, 0) => 1,
+                Location::new(2, 0) => 1,
             },
+            armour: 1,
+            decoy: false,
         }
     }
     fn ship_vert_4() -> Ship {
         Ship {
             coords: vec![
-                Location { x: 1, y: 1 },
-                Location { x: 1, y: 2 },
-                Location { x: 1, y: 3 },
-                Location { x: 1, y: 4 },
+                Location::new(1, 1),
+                Location::new(1, 2),
+                Location::new(1, 3),
+                Location::new(1, 4),
             ],
-            found: hashmap! {
-                Location { x: 1, y: 1 } => false,
-                Location { x: 1, y: 2 } => false,
-                Location { x: 1, y: 3 } => false,
-                Location { x: 1, y: 4 } => false,
+            hit_points: hashmap! {
+                Location::new(1, 1) => 1,
+                Location::new(1, 2) => 1,
+                Location::new(1, 3) => 1,
+                Location::new(1, 4) => 1,
             },
+            armour: 1,
+            decoy: false,
         }
     }
 
+    #[test]
+    fn test_ship_new_any_dir() {
+        let ship = Ship::new(Location::new(3, 3), Dir::Left, 3);
+        assert_eq!(
+            ship.get_coords(),
+            &[
+                Location::new(3, 3),
+                Location::new(2, 3),
+                Location::new(1, 3)
+            ]
+        );
+        let ship = Ship::new(Location::new(3, 3), Dir::Down, 2);
+        assert_eq!(
+            ship.get_coords(),
+            &[Location::new(3, 3), Location::new(3, 2)]
+        );
+
+        let mut grid = Grid::new(5);
+        assert_eq!(
+            grid.add_ship(Ship::new(Location::new(1, 0), Dir::Left, 3)),
+            Err(AddShipError::ShipOutOfBounds)
+        );
+        grid.add_ship(Ship::new(Location::new(2, 0), Dir::Left, 3))
+            .unwrap();
+    }
+
     #[test]
     fn test_ship_new() {
         assert_eq!(
-            Ship::new(Location { x: 0, y: 0 }, ShipDir::Horz, 3),
+            Ship::new(Location::new(0, 0), ShipDir::Horz, 3),
             ship_horz_3()
         );
         assert_eq!(
-            Ship::new(Location { x: 1, y: 1 }, ShipDir::Vert, 4),
+            Ship::new(Location::new(1, 1), ShipDir::Vert, 4),
             ship_vert_4()
         );
     }
 
+    #[test]
+    fn test_ship_new_on_torus() {
+        assert_eq!(
+            Ship::new_on(Location::new(0, 0), ShipDir::Horz, 3, Topology::Torus, 10),
+            ship_horz_3()
+        );
+        let wrapped = Ship::new_on(Location::new(8, 9), ShipDir::Vert, 3, Topology::Torus, 10);
+        assert_eq!(
+            wrapped.get_coords(),
+            [
+                Location::new(8, 9),
+                Location::new(8, 0),
+                Location::new(8, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_add_ship_torus() {
+        let mut flat = Grid::new(5);
+        let ship = flat.new_ship(Location::new(3, 0), ShipDir::Horz, 3);
+        assert_eq!(flat.add_ship(ship), Err(AddShipError::ShipOutOfBounds));
+
+        let mut torus = Grid::with_topology(5, Topology::Torus);
+        let ship = torus.new_ship(Location::new(3, 0), ShipDir::Horz, 3);
+        assert_eq!(torus.add_ship(ship), Ok(()));
+        let ship = torus.new_ship(Location::new(0, 4), ShipDir::Vert, 2);
+        assert_eq!(torus.add_ship(ship), Err(AddShipError::ShipOverlap));
+        let ship = torus.new_ship(Location::new(0, 1), ShipDir::Horz, 6);
+        assert_eq!(torus.add_ship(ship), Err(AddShipError::ShipTooLong));
+        // the start of the ship still has to be on the board
+        let ship = torus.new_ship(Location::new(5, 2), ShipDir::Horz, 2);
+        assert_eq!(torus.add_ship(ship), Err(AddShipError::ShipOutOfBounds));
+    }
+
+    #[test]
+    fn test_placements() {
+        // a destroyer fits 2 * 3 * 4 ways on a 4x4 square board
+        assert_eq!(placements(4, Topology::Flat, 2).len(), 24);
+        assert_eq!(placements(4, Topology::Torus, 2).len(), 32);
+        // and on hexes there is a third axis too
+        assert_eq!(placements(4, Topology::Hex, 2).len(), 33);
+        assert!(placements(4, Topology::Torus, 5).is_empty());
+    }
+
+    #[test]
+    fn test_hex_display() {
+        let mut grid = Grid::with_topology(3, Topology::Hex);
+        grid.add_ship(Ship::new_hex(HexCoord::new(2, 0), HexDir::SouthWest, 3))
+            .unwrap();
+        assert_eq!(
+            grid.get_display(true).to_string(),
+            "  1 2 3 \nA . . o \nB  . o . \nC   o . . \n"
+        );
+    }
+
+    #[test]
+    fn test_layered_grid() {
+        let mut grid = Grid::new(3).with_depths(1);
+        let sub = Ship::new(Location::at_depth(0, 1, 1), ShipDir::Horz, 2);
+        assert_eq!(sub.get_coords()[1], Location::at_depth(1, 1, 1));
+        grid.add_ship(sub).unwrap();
+        // the same spot on the surface is still free
+        grid.add_ship(Ship::new(Location::new(0, 1), ShipDir::Horz, 3))
+            .unwrap();
+        let too_deep = Ship::new(Location::at_depth(0, 0, 2), ShipDir::Horz, 2);
+        assert_eq!(grid.add_ship(too_deep), Err(AddShipError::ShipOutOfBounds));
+
+        assert_eq!(grid.guess_grid(Location::at_depth(2, 1, 1)), None);
+        assert_eq!(grid.guess_grid(Location::at_depth(1, 1, 1)), Some(0));
+        assert_eq!(
+            grid.get_display(true).to_string(),
+            "Surface   Depth 1\n  1 2 3     1 2 3 \nA . . .   A . . . \n\
+             B o o o   B o x . \nC . . .   C . . . \n"
+        );
+    }
+
+    #[test]
+    fn test_armour() {
+        let mut grid = Grid::new(3);
+        let mut ship = Ship::new(Location::new(0, 0), ShipDir::Horz, 2);
+        ship.set_armour(2);
+        grid.add_ship(ship).unwrap();
+
+        grid.guess_grid(Location::new(0, 0));
+        assert_eq!(grid.ships[0].hit_points(Location::new(0, 0)), Some(1));
+        assert_eq!(grid.get_all_found(), vec![]);
+        assert_eq!(grid.get_all_dented(), vec![Location::new(0, 0)]);
+        grid.guess_grid(Location::new(0, 0));
+        grid.guess_grid(Location::new(1, 0));
+        assert_eq!(grid.get_all_found(), vec![Location::new(0, 0)]);
+        assert!(!grid.check_loss());
+        assert_eq!(
+            grid.get_display(false).to_string(),
+            "  1 2 3 \nA x + . \nB . . . \nC . . . \n"
+        );
+        grid.guess_grid(Location::new(1, 0));
+        assert!(grid.check_loss());
+    }
+
+    #[test]
+    fn test_repair() {
+        let mut grid = Grid::new(3);
+        grid.add_ship(Ship::new(Location::new(0, 0), ShipDir::Horz, 2))
+            .unwrap();
+        assert!(!grid.repair(Location::new(0, 0)));
+        grid.guess_grid(Location::new(0, 0));
+        assert!(grid.repair(Location::new(0, 0)));
+        assert_eq!(grid.get_all_found(), vec![]);
+
+        // sunk ships are beyond saving
+        grid.guess_grid(Location::new(0, 0));
+        grid.guess_grid(Location::new(1, 0));
+        assert!(!grid.repair(Location::new(0, 0)));
+        assert!(grid.check_loss());
+    }
+
+    #[test]
+    fn test_layout_text() {
+        let text = "\
+            AA...
+            .....
+            B.CCC
+            B....
+            B....
+        ";
+        let grid: Grid = text.parse().unwrap();
+        assert_eq!(grid.size, 5);
+        assert_eq!(
+            grid.ships,
+            vec![
+                Ship::new(Location::new(0, 0), ShipDir::Horz, 2),
+                Ship::new(Location::new(0, 2), ShipDir::Vert, 3),
+                Ship::new(Location::new(2, 2), ShipDir::Horz, 3),
+            ]
+        );
+        assert_eq!(grid.to_string(), "AA...\n.....\nB.CCC\nB....\nB....\n");
+
+        let mut layered = Grid::new(3).with_depths(1);
+        layered
+            .add_ship(Ship::new(Location::at_depth(0, 1, 1), ShipDir::Horz, 3))
+            .unwrap();
+        let text = layered.to_string();
+        assert_eq!(text, "...\n...\n...\n\n...\nAAA\n...\n");
+        assert_eq!(text.parse::<Grid>().unwrap(), layered);
+    }
+
+    #[test]
+    fn test_layout_text_errors() {
+        assert_eq!("".parse::<Grid>(), Err(ParseGridError::Empty));
+        assert_eq!("..\n...".parse::<Grid>(), Err(ParseGridError::NotSquare(2)));
+        assert_eq!(
+            "A.\n.#".parse::<Grid>(),
+            Err(ParseGridError::UnknownCell('#'))
+        );
+        assert_eq!(
+            "A.\n.A".parse::<Grid>(),
+            Err(ParseGridError::NotStraight('A'))
+        );
+        assert_eq!(
+            "A.A\n...\n...".parse::<Grid>(),
+            Err(ParseGridError::NotStraight('A'))
+        );
+        assert_eq!(
+            "..\n..\n\n..".parse::<Grid>(),
+            Err(ParseGridError::LayerSize(1))
+        );
+    }
+
+    #[test]
+    fn test_place_fleet() {
+        let mut grid = Grid::new(5);
+        grid.add_ship(Ship::new(Location::new(0, 0), ShipDir::Horz, 2))
+            .unwrap();
+        let bad = vec![
+            Ship::new(Location::new(0, 1), ShipDir::Horz, 3),
+            Ship::new(Location::new(1, 0), ShipDir::Vert, 2),
+            Ship::new(Location::new(4, 3), ShipDir::Vert, 3),
+        ];
+        assert_eq!(
+            grid.place_fleet(bad),
+            Err(PlaceFleetError(vec![
+                (1, AddShipError::ShipOverlap),
+                (2, AddShipError::ShipOutOfBounds)
+            ]))
+        );
+        // the good ship wasn't placed either
+        assert_eq!(grid.ships.len(), 1);
+
+        let good = vec![
+            Ship::new(Location::new(0, 1), ShipDir::Horz, 3),
+            Ship::new(Location::new(4, 2), ShipDir::Vert, 3),
+        ];
+        grid.place_fleet(good).unwrap();
+        assert_eq!(grid.ships.len(), 3);
+    }
+
+    #[test]
+    fn test_decoys() {
+        let mut grid = Grid::new(3);
+        grid.add_ship(Ship::new(Location::new(0, 0), ShipDir::Horz, 2))
+            .unwrap();
+        let mut decoy = Ship::new(Location::new(0, 2), ShipDir::Horz, 2);
+        decoy.set_decoy(true);
+        grid.add_ship(decoy).unwrap();
+        assert_eq!(
+            grid.get_display(true).to_string(),
+            "  1 2 3 \nA o o . \nB . . . \nC d d . \n"
+        );
+
+        assert_eq!(grid.guess_grid(Location::new(0, 2)), Some(1));
+        grid.guess_grid(Location::new(0, 0));
+        grid.guess_grid(Location::new(1, 0));
+        // the decoy is still afloat, but it doesn't matter
+        assert!(grid.check_loss());
+    }
+
     #[test]
     fn test_ship_guess() {
         {
             let mut s = ship_horz_3();
-            assert!(s.guess(Location { x: 0, y: 0 }));
+            assert!(s.guess(Location::new(0, 0)));
             assert_eq!(
                 s,
                 Ship {
                     coords: vec![
-                        Location { x: 0, y: 0 },
-                        Location { x: 1, y: 0 },
-                        Location { x: 2, y: 0 },
+                        Location::new(0, 0),
+                        Location::new(1, 0),
+                        Location::new(2, 0),
                     ],
-                    found: hashmap! {
-                        Location { x: 0, y: 0 } => true,
-                        Location { x: 1, y: 0 } => false,
-                        Location { x: 2, y: 0 } => false,
+                    hit_points: hashmap! {
+                        Location::new(0, 0) => 0,
+                        Location::new(1, 0) => 1,
+                        Location::new(2, 0) => 1,
                     },
+                    armour: 1,
+                    decoy: false,
                 }
             );
         }
         {
             let mut s = ship_horz_3();
-            assert!(!s.guess(Location { x: 10, y: 10 }));
+            assert!(!s.guess(Location::new(10, 10)));
             assert_eq!(s, ship_horz_3());
         }
     }
@@ -0,0 +1,533 @@
+//! Battleship solitaire (also known as Bimaru): a single player puzzle where the fleet is hidden
+//! on a grid, and has to be deduced from how many ship cells are in each row and column plus a
+//! few revealed cells. Unlike the two player game, ships may not touch, not even diagonally.
+
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{
+    location::{row_label, Location},
+    ship::{Grid, Ship, ShipClass, ShipDir},
+};
+
+/// Which part of a ship a cell is, as seen on the screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Segment {
+    Single,
+    Middle,
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hint {
+    Water,
+    Ship(Segment),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Puzzle {
+    pub size: i32,
+    pub fleet: Vec<ShipClass>,
+    pub row_counts: Vec<i32>,
+    pub col_counts: Vec<i32>,
+    pub hints: Vec<(Location, Hint)>,
+}
+
+/// What the player has marked a cell as while solving.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mark {
+    Unknown,
+    Water,
+    Ship,
+}
+
+pub fn segment_of(ship: &Ship, coord: Location) -> Option<Segment> {
+    let coords = ship.get_coords();
+    let index = coords.iter().position(|&c| c == coord)?;
+    if coords.len() == 1 {
+        return Some(Segment::Single);
+    }
+    let horizontal = coords[0].y == coords[1].y;
+    Some(match (index, horizontal) {
+        (0, true) => Segment::Left,
+        (0, false) => Segment::Top,
+        (i, true) if i == coords.len() - 1 => Segment::Right,
+        (i, false) if i == coords.len() - 1 => Segment::Bottom,
+        _ => Segment::Middle,
+    })
+}
+
+impl Puzzle {
+    /// Creates a puzzle from a finished layout, with no hints.
+    pub fn from_solution(solution: &Grid, fleet: &[ShipClass]) -> Self {
+        let mut row_counts = vec![0; solution.size as usize];
+        let mut col_counts = vec![0; solution.size as usize];
+        for c in solution.get_all() {
+            row_counts[c.y as usize] += 1;
+            col_counts[c.x as usize] += 1;
+        }
+        Self {
+            size: solution.size,
+            fleet: fleet.to_vec(),
+            row_counts,
+            col_counts,
+            hints: vec![],
+        }
+    }
+
+    pub fn hint_at(&self, coord: Location) -> Option<Hint> {
+        self.hints
+            .iter()
+            .find(|(l, _)| *l == coord)
+            .map(|&(_, h)| h)
+    }
+
+    /// The hint that would reveal the cell at `coord` in `solution`.
+    pub fn hint_from_solution(solution: &Grid, coord: Location) -> Hint {
+        solution
+            .ships
+            .iter()
+            .find_map(|s| segment_of(s, coord))
+            .map_or(Hint::Water, Hint::Ship)
+    }
+
+    /// Checks whether a ship could go here using only the hints, before anything else is placed.
+    fn allows(&self, ship: &Ship) -> bool {
+        for &c in ship.get_coords() {
+            if c.x < 0 || c.y < 0 || c.x >= self.size || c.y >= self.size {
+                return false;
+            }
+        }
+        let in_row = |y: i32| ship.get_coords().iter().filter(|c| c.y == y).count() as i32;
+        let in_col = |x: i32| ship.get_coords().iter().filter(|c| c.x == x).count() as i32;
+        for &c in ship.get_coords() {
+            if in_row(c.y) > self.row_counts[c.y as usize]
+                || in_col(c.x) > self.col_counts[c.x as usize]
+            {
+                return false;
+            }
+        }
+
+        for &(l, hint) in &self.hints {
+            match (hint, segment_of(ship, l)) {
+                (Hint::Water, Some(_)) => return false,
+                (Hint::Ship(wanted), Some(seg)) if wanted != seg => return false,
+                // a cell that has to be a ship can't be next to a different ship
+                (Hint::Ship(_), None)
+                    if ship.get_coords().iter().any(|&c| c.check_neighbour(l)) =>
+                {
+                    return false
+                }
+                _ => {}
+            }
+        }
+        true
+    }
+
+    /// Finds up to `limit` different solutions to the puzzle.
+    pub fn solve(&self, limit: usize) -> Vec<Grid> {
+        let mut order = self.fleet.clone();
+        // long ships have the fewest places to go, so trying them first prunes the search faster
+        order.sort_by_key(|c| std::cmp::Reverse(c.size()));
+
+        let mut candidates = vec![];
+        for class in &order {
+            let mut ships = vec![];
+            for dir in [ShipDir::Horz, ShipDir::Vert] {
+                for y in 0..self.size {
+                    for x in 0..self.size {
+                        let ship = Ship::new(Location::new(x, y), dir, class.size());
+                        if self.allows(&ship) {
+                            ships.push(ship);
+                        }
+                    }
+                }
+            }
+            candidates.push(ships);
+        }
+
+        let mut solver = Solver {
+            puzzle: self,
+            order,
+            candidates,
+            placed: vec![],
+            rows: vec![0; self.size as usize],
+            cols: vec![0; self.size as usize],
+            solutions: vec![],
+            limit,
+        };
+        solver.search();
+        solver.solutions
+    }
+
+    pub fn is_unique(&self) -> bool {
+        self.solve(2).len() == 1
+    }
+
+    /// Makes a new puzzle with exactly one solution, returning it along with the solution.
+    pub fn generate(
+        size: i32,
+        fleet: &[ShipClass],
+        difficulty: Difficulty,
+        rng: &mut impl Rng,
+    ) -> Option<(Puzzle, Grid)> {
+        let solution = random_layout(size, fleet, rng)?;
+        let mut puzzle = Puzzle::from_solution(&solution, fleet);
+
+        // keep revealing a cell where two possible solutions disagree until only one is left
+        loop {
+            let solutions = puzzle.solve(2);
+            if solutions.len() < 2 {
+                break;
+            }
+            let mut differing = vec![];
+            for y in 0..size {
+                for x in 0..size {
+                    let l = Location::new(x, y);
+                    if Self::hint_from_solution(&solutions[0], l)
+                        != Self::hint_from_solution(&solutions[1], l)
+                    {
+                        differing.push(l);
+                    }
+                }
+            }
+            let &l = differing.choose(rng)?;
+            puzzle
+                .hints
+                .push((l, Self::hint_from_solution(&solution, l)));
+        }
+
+        match difficulty {
+            Difficulty::Easy => {
+                let mut extra: Vec<Location> = solution
+                    .get_all()
+                    .into_iter()
+                    .filter(|&l| puzzle.hint_at(l).is_none())
+                    .collect();
+                extra.shuffle(rng);
+                for l in extra.into_iter().take(fleet.len() / 2 + 1) {
+                    puzzle
+                        .hints
+                        .push((l, Self::hint_from_solution(&solution, l)));
+                }
+            }
+            Difficulty::Medium => {}
+            Difficulty::Hard => {
+                // take away every hint that isn't needed to keep the solution unique
+                let mut hints = puzzle.hints.clone();
+                hints.shuffle(rng);
+                for hint in hints {
+                    let index = puzzle.hints.iter().position(|&h| h == hint).unwrap();
+                    puzzle.hints.remove(index);
+                    if !puzzle.is_unique() {
+                        puzzle.hints.insert(index, hint);
+                    }
+                }
+            }
+        }
+
+        Some((puzzle, solution))
+    }
+
+    /// Checks whether the cells marked as ships are exactly the ones in `solution`.
+    pub fn check(&self, marks: &[Mark], solution: &Grid) -> bool {
+        let all = solution.get_all();
+        (0..self.size).all(|y| {
+            (0..self.size).all(|x| {
+                let l = Location::new(x, y);
+                let marked = marks[(y * self.size + x) as usize] == Mark::Ship;
+                marked == all.contains(&l)
+            })
+        })
+    }
+
+    pub fn get_display<'p>(&'p self, marks: &'p [Mark]) -> PuzzleDisplay<'p> {
+        PuzzleDisplay {
+            puzzle: self,
+            marks,
+        }
+    }
+}
+
+fn random_layout(size: i32, fleet: &[ShipClass], rng: &mut impl Rng) -> Option<Grid> {
+    let mut order = fleet.to_vec();
+    order.sort_by_key(|c| std::cmp::Reverse(c.size()));
+
+    // placing ships one at a time can paint itself into a corner, so just start again when it does
+    'attempt: for _ in 0..100 {
+        let mut grid = Grid::new(size);
+        for class in &order {
+            let mut candidates = vec![];
+            for dir in [ShipDir::Horz, ShipDir::Vert] {
+                for y in 0..size {
+                    for x in 0..size {
+                        let ship = Ship::new(Location::new(x, y), dir, class.size());
+                        let touching = ship
+                            .get_coords()
+                            .iter()
+                            .any(|&c| grid.get_all().iter().any(|&o| c.check_neighbour(o)));
+                        if !touching && grid.check_ship(&ship).is_ok() {
+                            candidates.push(ship);
+                        }
+                    }
+                }
+            }
+            match candidates.choose(rng) {
+                Some(ship) => grid.add_ship(ship.clone()).unwrap(),
+                None => continue 'attempt,
+            }
+        }
+        return Some(grid);
+    }
+    None
+}
+
+struct Solver<'p> {
+    puzzle: &'p Puzzle,
+    order: Vec<ShipClass>,
+    // candidates[i] is every place that the i-th ship in `order` could go
+    candidates: Vec<Vec<Ship>>,
+    // the index into `candidates` of each ship placed so far
+    placed: Vec<usize>,
+    rows: Vec<i32>,
+    cols: Vec<i32>,
+    solutions: Vec<Grid>,
+    limit: usize,
+}
+
+impl<'p> Solver<'p> {
+    fn placed_ships(&self) -> impl Iterator<Item = &Ship> {
+        self.placed
+            .iter()
+            .enumerate()
+            .map(|(depth, &i)| &self.candidates[depth][i])
+    }
+
+    fn fits(&self, ship: &Ship) -> bool {
+        for &c in ship.get_coords() {
+            if self
+                .placed_ships()
+                .any(|s| s.get_coords().iter().any(|&o| c.check_neighbour(o)))
+            {
+                return false;
+            }
+        }
+        let mut rows = self.rows.clone();
+        let mut cols = self.cols.clone();
+        for &c in ship.get_coords() {
+            rows[c.y as usize] += 1;
+            cols[c.x as usize] += 1;
+            if rows[c.y as usize] > self.puzzle.row_counts[c.y as usize]
+                || cols[c.x as usize] > self.puzzle.col_counts[c.x as usize]
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn add_counts(&mut self, ship: &Ship, amount: i32) {
+        for &c in ship.get_coords() {
+            self.rows[c.y as usize] += amount;
+            self.cols[c.x as usize] += amount;
+        }
+    }
+
+    fn is_solved(&self) -> bool {
+        if self.rows != self.puzzle.row_counts || self.cols != self.puzzle.col_counts {
+            return false;
+        }
+        self.puzzle.hints.iter().all(|&(l, hint)| match hint {
+            Hint::Water => true,
+            Hint::Ship(_) => self.placed_ships().any(|s| s.get_coords().contains(&l)),
+        })
+    }
+
+    fn search(&mut self) {
+        if self.solutions.len() >= self.limit {
+            return;
+        }
+        let depth = self.placed.len();
+        if depth == self.order.len() {
+            if self.is_solved() {
+                let mut grid = Grid::new(self.puzzle.size);
+                for s in self.placed_ships() {
+                    grid.ships.push(s.clone());
+                }
+                self.solutions.push(grid);
+            }
+            return;
+        }
+
+        // ships of the same length are interchangeable, so only try them in increasing order to
+        // avoid finding the same layout more than once
+        let start = match self.placed.last() {
+            Some(&prev) if self.order[depth - 1].size() == self.order[depth].size() => prev + 1,
+            _ => 0,
+        };
+        for i in start..self.candidates[depth].len() {
+            let ship = self.candidates[depth][i].clone();
+            if !self.fits(&ship) {
+                continue;
+            }
+            self.add_counts(&ship, 1);
+            self.placed.push(i);
+            self.search();
+            self.placed.pop();
+            self.add_counts(&ship, -1);
+        }
+    }
+}
+
+pub struct PuzzleDisplay<'p> {
+    puzzle: &'p Puzzle,
+    marks: &'p [Mark],
+}
+
+impl<'p> std::fmt::Display for PuzzleDisplay<'p> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let size = self.puzzle.size;
+        let label_width = row_label(size - 1).len();
+        write!(f, "{:1$}", "", label_width + 2)?;
+        for x in 1..size + 1 {
+            write!(f, "{x:<3}")?;
+        }
+        writeln!(f)?;
+
+        for y in 0..size {
+            write!(f, "{:label_width$}  ", row_label(y))?;
+            for x in 0..size {
+                let cur = Location::new(x, y);
+                let c = match self.puzzle.hint_at(cur) {
+                    Some(Hint::Water) => '~',
+                    Some(Hint::Ship(Segment::Single)) => 'O',
+                    Some(Hint::Ship(Segment::Middle)) => '#',
+                    Some(Hint::Ship(Segment::Top)) => '^',
+                    Some(Hint::Ship(Segment::Bottom)) => 'v',
+                    Some(Hint::Ship(Segment::Left)) => '<',
+                    Some(Hint::Ship(Segment::Right)) => '>',
+                    None => match self.marks[(y * size + x) as usize] {
+                        Mark::Unknown => '.',
+                        Mark::Water => '-',
+                        Mark::Ship => 'x',
+                    },
+                };
+                write!(f, "{c}  ")?;
+            }
+            writeln!(f, "| {}", self.puzzle.row_counts[y as usize])?;
+        }
+
+        write!(f, "   ")?;
+        for x in 0..size {
+            write!(f, "{:<3}", self.puzzle.col_counts[x as usize])?;
+        }
+        writeln!(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn small_solution() -> Grid {
+        let mut grid = Grid::new(5);
+        grid.add_ship(Ship::new(Location::new(0, 0), ShipDir::Horz, 3))
+            .unwrap();
+        grid.add_ship(Ship::new(Location::new(4, 2), ShipDir::Vert, 2))
+            .unwrap();
+        grid
+    }
+
+    #[test]
+    fn test_segment_of() {
+        let horz = Ship::new(Location::new(0, 0), ShipDir::Horz, 3);
+        assert_eq!(segment_of(&horz, Location::new(0, 0)), Some(Segment::Left));
+        assert_eq!(
+            segment_of(&horz, Location::new(1, 0)),
+            Some(Segment::Middle)
+        );
+        assert_eq!(segment_of(&horz, Location::new(2, 0)), Some(Segment::Right));
+        assert_eq!(segment_of(&horz, Location::new(3, 0)), None);
+
+        let vert = Ship::new(Location::new(2, 2), ShipDir::Vert, 2);
+        assert_eq!(segment_of(&vert, Location::new(2, 2)), Some(Segment::Top));
+        assert_eq!(
+            segment_of(&vert, Location::new(2, 3)),
+            Some(Segment::Bottom)
+        );
+    }
+
+    #[test]
+    fn test_from_solution_counts() {
+        let puzzle = Puzzle::from_solution(
+            &small_solution(),
+            &[ShipClass::Submarine, ShipClass::Destroyer],
+        );
+        assert_eq!(puzzle.row_counts, vec![3, 0, 1, 1, 0]);
+        assert_eq!(puzzle.col_counts, vec![1, 1, 1, 0, 2]);
+    }
+
+    #[test]
+    fn test_solve_with_hints() {
+        let solution = small_solution();
+        let mut puzzle =
+            Puzzle::from_solution(&solution, &[ShipClass::Submarine, ShipClass::Destroyer]);
+        assert!(puzzle.is_unique());
+        assert_eq!(puzzle.solve(2), vec![solution.clone()]);
+
+        // splitting the destroyer's rows apart leaves no room for it
+        puzzle.row_counts = vec![3, 0, 1, 0, 1];
+        assert!(puzzle.solve(2).is_empty());
+
+        puzzle.row_counts = vec![3, 0, 1, 1, 0];
+        puzzle.hints.push((Location::new(0, 0), Hint::Water));
+        assert!(puzzle.solve(2).is_empty());
+    }
+
+    #[test]
+    fn test_generate_unique() {
+        let mut rng = ChaCha8Rng::seed_from_u64(5);
+        for difficulty in [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard] {
+            let (puzzle, solution) = Puzzle::generate(
+                8,
+                &[
+                    ShipClass::Battleship,
+                    ShipClass::Cruiser,
+                    ShipClass::Destroyer,
+                ],
+                difficulty,
+                &mut rng,
+            )
+            .unwrap();
+            assert_eq!(puzzle.solve(2), vec![solution.clone()]);
+            for &(l, hint) in &puzzle.hints {
+                assert_eq!(Puzzle::hint_from_solution(&solution, l), hint);
+            }
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let solution = small_solution();
+        let puzzle =
+            Puzzle::from_solution(&solution, &[ShipClass::Submarine, ShipClass::Destroyer]);
+        let mut marks = vec![Mark::Unknown; 25];
+        for l in solution.get_all() {
+            marks[(l.y * 5 + l.x) as usize] = Mark::Ship;
+        }
+        assert!(puzzle.check(&marks, &solution));
+        marks[24] = Mark::Ship;
+        assert!(!puzzle.check(&marks, &solution));
+    }
+}
@@ -0,0 +1,225 @@
+//! How each player did over a game, worked out by replaying its actions.
+
+use std::fmt::{self, Display};
+
+use serde::Serialize;
+
+use crate::{
+    game::{Action, Game, Player},
+    ship::ShipClass,
+};
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct GameStats {
+    pub players: [PlayerStats; 2],
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PlayerStats {
+    pub shots: u32,
+    /// Shots that struck a ship, including armour that held and decoys.
+    pub hits: u32,
+    /// Hits as a fraction of shots, or 0 if the player never fired.
+    pub hit_rate: f64,
+    /// The most hits in a row, even across turns.
+    pub longest_streak: u32,
+    pub turns: u32,
+    /// How the player's own ships fared, in the order they were placed. Decoys are left out.
+    pub fleet: Vec<ShipStats>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ShipStats {
+    pub class: ShipClass,
+    /// How many shots the opponent fired from their first hit on the ship up to and including the
+    /// one that sank it, or none if it wasn't sunk.
+    pub first_hit_to_sink: Option<u32>,
+    /// How many of the opponent's turns the ship was still afloat at the end of.
+    pub turns_survived: u32,
+}
+
+/// What happened to one ship while replaying.
+#[derive(Clone, Copy, Default)]
+struct ShipRecord {
+    first_hit: Option<u32>,
+    /// The shot and the turn that sank it.
+    sunk: Option<(u32, u32)>,
+}
+
+impl GameStats {
+    pub fn from_game(game: &Game) -> Self {
+        let mut replay = Game::with_rules(game.rules().clone(), game.seed());
+        let mut shots = [0; 2];
+        let mut hits = [0; 2];
+        let mut streaks = [0; 2];
+        let mut longest_streak = [0; 2];
+        let mut turns = [0; 2];
+        let mut last_turn = None;
+        let mut records = [vec![], vec![]];
+
+        for action in game.actions() {
+            let actor = match *action {
+                Action::Guess { player, .. } | Action::Repair { player, .. } => Some(player),
+                _ => None,
+            };
+            if let Some(player) = actor {
+                if last_turn != Some(player) {
+                    turns[player as usize] += 1;
+                    last_turn = Some(player);
+                }
+            }
+
+            replay.apply(action);
+
+            let Action::Guess { player, loc } = *action else {
+                continue;
+            };
+            let p = player as usize;
+            let grid = replay.get_grid(player.other());
+            let ships = &mut records[player.other() as usize];
+            ships.resize(grid.ships.len(), ShipRecord::default());
+            shots[p] += 1;
+
+            match grid
+                .ships
+                .iter()
+                .position(|s| s.get_coords().contains(&loc))
+            {
+                Some(i) => {
+                    hits[p] += 1;
+                    streaks[p] += 1;
+                    longest_streak[p] = longest_streak[p].max(streaks[p]);
+                    let record = &mut ships[i];
+                    record.first_hit.get_or_insert(shots[p]);
+                    if grid.ships[i].is_sunk() && record.sunk.is_none() {
+                        record.sunk = Some((shots[p], turns[p]));
+                    }
+                }
+                None => streaks[p] = 0,
+            }
+        }
+
+        let players = [Player::Player1, Player::Player2].map(|player| {
+            let p = player as usize;
+            let attacker = player.other() as usize;
+            let grid = game.get_grid(player);
+            let classes = game.pieces(player);
+            let fleet = (grid.ships.iter().enumerate())
+                .filter(|(_, ship)| !ship.is_decoy())
+                .map(|(i, _)| {
+                    let record = records[p].get(i).copied().unwrap_or_default();
+                    ShipStats {
+                        class: classes[i],
+                        first_hit_to_sink: match (record.first_hit, record.sunk) {
+                            (Some(first), Some((sunk, _))) => Some(sunk - first + 1),
+                            _ => None,
+                        },
+                        turns_survived: match record.sunk {
+                            Some((_, turn)) => turn - 1,
+                            None => turns[attacker],
+                        },
+                    }
+                })
+                .collect();
+            PlayerStats {
+                shots: shots[p],
+                hits: hits[p],
+                hit_rate: match shots[p] {
+                    0 => 0.0,
+                    n => hits[p] as f64 / n as f64,
+                },
+                longest_streak: longest_streak[p],
+                turns: turns[p],
+                fleet,
+            }
+        });
+        Self { players }
+    }
+
+    pub fn player(&self, player: Player) -> &PlayerStats {
+        &self.players[player as usize]
+    }
+}
+
+impl Display for GameStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for player in [Player::Player1, Player::Player2] {
+            let stats = self.player(player);
+            writeln!(
+                f,
+                "Player {}: {} shots, {} hits ({:.0}%), longest streak {}",
+                player.num(),
+                stats.shots,
+                stats.hits,
+                stats.hit_rate * 100.0,
+                stats.longest_streak
+            )?;
+            for ship in &stats.fleet {
+                write!(f, "  {}: ", ship.class.name())?;
+                match ship.first_hit_to_sink {
+                    Some(1) => write!(f, "sunk by the first hit")?,
+                    Some(n) => write!(f, "sunk in {n} shots from the first hit")?,
+                    None => write!(f, "never sunk")?,
+                }
+                writeln!(f, ", survived {} turns", ship.turns_survived)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::test_game;
+
+    #[test]
+    fn test_stats() {
+        let board = "AA..\nBBB.\n....\n....";
+        // player 1 finds the cruiser, misses, then sinks both ships
+        let shots = [
+            "B1", "D4", "D4", "C4", "B2", "C3", "B3", "C2", "A1", "D1", "A2",
+        ];
+        let game = test_game(
+            &[ShipClass::Destroyer, ShipClass::Cruiser],
+            [board, board],
+            &shots,
+        );
+        assert_eq!(game.winner(), Some(Player::Player1));
+
+        let stats = GameStats::from_game(&game);
+        let p1 = stats.player(Player::Player1);
+        assert_eq!((p1.shots, p1.hits, p1.turns), (6, 5, 6));
+        assert_eq!(p1.longest_streak, 4);
+        assert!((p1.hit_rate - 5.0 / 6.0).abs() < 1e-9);
+        assert_eq!(
+            stats.player(Player::Player2).fleet,
+            [
+                ShipStats {
+                    class: ShipClass::Destroyer,
+                    first_hit_to_sink: Some(2),
+                    turns_survived: 5,
+                },
+                ShipStats {
+                    class: ShipClass::Cruiser,
+                    first_hit_to_sink: Some(4),
+                    turns_survived: 3,
+                },
+            ]
+        );
+
+        let p2 = stats.player(Player::Player2);
+        assert_eq!((p2.shots, p2.hits, p2.longest_streak), (5, 0, 0));
+        assert_eq!(
+            stats.player(Player::Player1).fleet[0],
+            ShipStats {
+                class: ShipClass::Destroyer,
+                first_hit_to_sink: None,
+                turns_survived: 5,
+            }
+        );
+        assert!(stats
+            .to_string()
+            .contains("Player 1: 6 shots, 5 hits (83%), longest streak 4\n"));
+    }
+}
@@ -0,0 +1,247 @@
+//! SVG pictures of boards and finished games, for pasting into wiki pages and bug reports. Both
+//! players' ships are drawn, so these are for after the game rather than during it.
+
+use std::fmt::Write;
+
+use crate::{
+    game::{Action, Game, Player},
+    location::{rect, row_label, Location, Topology},
+    render::Cell,
+    ship::Grid,
+};
+
+/// How big each cell is, in pixels.
+const CELL: i32 = 30;
+/// Room left for the row letters and column numbers.
+const LABEL: i32 = 24;
+/// Room for the title above each layer.
+const TITLE: i32 = 22;
+/// Space between layers, and between the boards of a game.
+const GAP: i32 = 20;
+
+/// One board to draw, along with the shots fired at it in order.
+struct Panel<'a> {
+    title: String,
+    grid: &'a Grid,
+    shots: Vec<Location>,
+}
+
+impl Panel<'_> {
+    fn width(&self) -> i32 {
+        let indent = match self.grid.topology {
+            // each row of hexes sits half a cell to the right of the one above
+            Topology::Hex => (self.grid.size - 1) * CELL / 2,
+            _ => 0,
+        };
+        LABEL + self.grid.size * CELL + indent
+    }
+
+    fn layer_height(&self) -> i32 {
+        TITLE + LABEL + self.grid.size * CELL
+    }
+
+    fn height(&self) -> i32 {
+        (self.grid.depths + 1) * self.layer_height() + self.grid.depths * GAP
+    }
+
+    fn draw(&self, out: &mut String, left: i32, top: i32) {
+        let grid = self.grid;
+        for layer in 0..=grid.depths {
+            let top = top + layer * (self.layer_height() + GAP);
+            let title = match layer {
+                _ if grid.depths == 0 => self.title.clone(),
+                0 => format!("{} (surface)", self.title),
+                _ => format!("{} (depth {layer})", self.title),
+            };
+            text(out, left, top + 16, "start", 14, &title);
+
+            let top = top + TITLE;
+            for x in 0..grid.size {
+                let cx = left + LABEL + x * CELL + CELL / 2;
+                text(out, cx, top + 16, "middle", 12, &(x + 1).to_string());
+            }
+            for y in 0..grid.size {
+                let cy = top + LABEL + y * CELL + CELL / 2;
+                text(out, left + LABEL / 2, cy + 4, "middle", 12, &row_label(y));
+            }
+
+            for loc in rect(Location::at_depth(0, 0, layer), grid.size, grid.size) {
+                let mut x = left + LABEL + loc.x * CELL;
+                if grid.topology == Topology::Hex {
+                    x += loc.y * CELL / 2;
+                }
+                let y = top + LABEL + loc.y * CELL;
+                self.cell(out, loc, x, y);
+            }
+        }
+    }
+
+    fn cell(&self, out: &mut String, loc: Location, x: i32, y: i32) {
+        let cell = Cell::own(self.grid, loc);
+        let fill = match cell {
+            Cell::Water | Cell::Miss => "#dbeafe",
+            Cell::Decoy => "#d6d3d1",
+            Cell::Ship | Cell::Dented | Cell::Hit => "#64748b",
+            Cell::Sunk => "#7f1d1d",
+        };
+        writeln!(
+            out,
+            r##"<rect x="{x}" y="{y}" width="{CELL}" height="{CELL}" fill="{fill}" stroke="#94a3b8"/>"##
+        )
+        .unwrap();
+
+        let (cx, cy) = (x + CELL / 2, y + CELL / 2);
+        match cell {
+            Cell::Miss => writeln!(
+                out,
+                r##"<circle cx="{cx}" cy="{cy}" r="4" fill="#1e3a8a"/>"##
+            )
+            .unwrap(),
+            Cell::Dented => writeln!(
+                out,
+                r##"<circle cx="{cx}" cy="{cy}" r="8" fill="none" stroke="#f59e0b" stroke-width="3"/>"##
+            )
+            .unwrap(),
+            Cell::Hit | Cell::Sunk => {
+                let r = CELL / 2 - 7;
+                writeln!(
+                    out,
+                    r##"<path d="M{} {}L{} {}M{} {}L{} {}" stroke="#ef4444" stroke-width="3"/>"##,
+                    cx - r,
+                    cy - r,
+                    cx + r,
+                    cy + r,
+                    cx + r,
+                    cy - r,
+                    cx - r,
+                    cy + r
+                )
+                .unwrap()
+            }
+            _ => {}
+        }
+
+        // which shots landed here, counting from 1, in the corner of the cell
+        let order: Vec<String> = (self.shots.iter().enumerate())
+            .filter(|&(_, &shot)| shot == loc)
+            .map(|(i, _)| (i + 1).to_string())
+            .collect();
+        if !order.is_empty() {
+            text(out, x + CELL - 2, y + 9, "end", 8, &order.join(","));
+        }
+    }
+}
+
+fn text(out: &mut String, x: i32, y: i32, anchor: &str, size: i32, text: &str) {
+    writeln!(
+        out,
+        r#"<text x="{x}" y="{y}" text-anchor="{anchor}" font-size="{size}">{}</text>"#,
+        escape(text)
+    )
+    .unwrap();
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Lays the panels out left to right in one image.
+fn document(panels: &[Panel]) -> String {
+    let width = panels.iter().map(|p| p.width() + GAP).sum::<i32>() + GAP;
+    let height = panels.iter().map(|p| p.height()).max().unwrap_or(0) + 2 * GAP;
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}" font-family="sans-serif">"#
+    )
+    .unwrap();
+    writeln!(out, r#"<rect width="100%" height="100%" fill="white"/>"#).unwrap();
+    let mut left = GAP;
+    for panel in panels {
+        panel.draw(&mut out, left, GAP);
+        left += panel.width() + GAP;
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+/// A picture of one grid, showing every ship and every shot fired at it.
+pub fn grid(grid: &Grid) -> String {
+    document(&[Panel {
+        title: "Fleet".to_string(),
+        grid,
+        shots: vec![],
+    }])
+}
+
+/// Both players' grids next to each other, with each shot numbered in the order it was fired.
+pub fn game(game: &Game) -> String {
+    let panels = [Player::Player1, Player::Player2].map(|player| {
+        // the shots on this grid are the ones the other player fired
+        let shots = (game.actions().iter())
+            .filter_map(|a| match *a {
+                Action::Guess { player: p, loc } if p == player.other() => Some(loc),
+                _ => None,
+            })
+            .collect();
+        let title = match game.winner() {
+            Some(winner) if winner == player => format!("Player {} (winner)", player.num()),
+            _ => format!("Player {}", player.num()),
+        };
+        Panel {
+            title,
+            grid: game.get_grid(player),
+            shots,
+        }
+    });
+    document(&panels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{game::test_game, ship::ShipClass};
+
+    #[test]
+    fn test_grid() {
+        let board: Grid = "AA.\n...\n...".parse().unwrap();
+        let svg = grid(&board);
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>\n"));
+        // the background plus one per cell
+        assert_eq!(svg.matches("<rect").count(), 1 + 9);
+        assert_eq!(svg.matches(r##"fill="#64748b""##).count(), 2);
+    }
+
+    #[test]
+    fn test_game_shot_order() {
+        let played = test_game(
+            &[ShipClass::Destroyer],
+            ["AA.\n...\n...", "...\n...\nAA."],
+            &["C1", "C3", "C2"],
+        );
+
+        let svg = game(&played);
+        assert!(svg.contains(">Player 1 (winner)</text>"));
+        assert!(svg.contains(">Player 2</text>"));
+        // two cells of the sunk destroyer
+        assert_eq!(svg.matches(r##"fill="#7f1d1d""##).count(), 2);
+        assert_eq!(svg.matches(r##"<circle cx"##).count(), 1);
+        // player 1's shots are numbered 1 and 2, player 2's just 1
+        assert_eq!(svg.matches(r#"font-size="8">1</text>"#).count(), 2);
+        assert_eq!(svg.matches(r#"font-size="8">2</text>"#).count(), 1);
+    }
+
+    #[test]
+    fn test_layers_and_hexes() {
+        let board = Grid::with_topology(4, Topology::Hex).with_depths(1);
+        let svg = grid(&board);
+        assert!(svg.contains(">Fleet (depth 1)</text>"));
+        assert_eq!(svg.matches("<rect").count(), 1 + 2 * 16);
+        // the bottom row of hexes is shifted a cell and a half along
+        assert!(svg.contains(&format!(r#"<rect x="{}" y="#, GAP + LABEL + 3 * CELL / 2)));
+    }
+}
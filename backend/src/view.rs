@@ -0,0 +1,156 @@
+use serde::Serialize;
+
+use crate::{
+    game::Disclosure,
+    location::{Location, Topology},
+    ship::{Grid, ShipClass},
+};
+
+/// What a player can see of their opponent's grid: where they have shot and what they found,
+/// but not where the ships they haven't sunk yet are.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PlayerView {
+    pub size: i32,
+    pub topology: Topology,
+    /// How many layers there are below the surface.
+    pub depths: i32,
+    /// Hits on ships that are still afloat.
+    pub hits: Vec<Location>,
+    /// Armoured cells that have been hit but not destroyed yet, so they can be shot again.
+    pub dented: Vec<Location>,
+    pub misses: Vec<Location>,
+    /// Sunk ships, with their class if the rules say what it was. When the rules only tell hits
+    /// from misses, sunk ships stay in `hits` instead.
+    pub sunk: Vec<(Option<ShipClass>, Vec<Location>)>,
+    /// Decoys that have been "sunk" and revealed as decoys. Unless the rules reveal them, sunk
+    /// decoys show up in `sunk` like any other ship.
+    pub decoys: Vec<Vec<Location>>,
+    /// The ships that haven't been sunk yet, including any decoys. If the rules don't name sunk
+    /// ships, this is a best guess that only goes by their size.
+    pub remaining: Vec<ShipClass>,
+}
+
+impl PlayerView {
+    /// The view of `grid`, whose ships were placed in the order given by `fleet`, showing only
+    /// what `disclosure` allows.
+    pub fn of_grid(
+        grid: &Grid,
+        fleet: &[ShipClass],
+        disclosure: Disclosure,
+        reveal_decoys: bool,
+    ) -> Self {
+        let mut hits = vec![];
+        let mut dented = vec![];
+        let mut sunk = vec![];
+        let mut decoys = vec![];
+        let mut remaining = vec![];
+        for (ship, &class) in grid.ships.iter().zip(fleet) {
+            let announced = ship.is_sunk() && disclosure != Disclosure::HitOrMiss;
+            if announced && ship.is_decoy() && reveal_decoys {
+                decoys.push(ship.get_coords().to_vec());
+            } else if announced {
+                let class = (disclosure == Disclosure::Class).then_some(class);
+                sunk.push((class, ship.get_coords().to_vec()));
+            } else {
+                remaining.push(class);
+                for &c in ship.get_coords() {
+                    if ship.is_found(c) {
+                        hits.push(c);
+                    } else if ship.is_dented(c) {
+                        dented.push(c);
+                    }
+                }
+            }
+        }
+        // ships that haven't been placed yet are still out there somewhere
+        remaining.extend(fleet.iter().skip(grid.ships.len()));
+        if disclosure == Disclosure::Sunk {
+            // the player only sees the shape of what they sank, so cross off a class that fits
+            remaining = fleet.to_vec();
+            for coords in sunk.iter().map(|(_, c)| c).chain(&decoys) {
+                let fits = remaining.iter().position(|class| {
+                    class.size() as usize == coords.len()
+                        && class.layers(grid.depths).contains(&coords[0].layer)
+                });
+                if let Some(i) = fits {
+                    remaining.remove(i);
+                }
+            }
+        }
+
+        Self {
+            size: grid.size,
+            topology: grid.topology,
+            depths: grid.depths,
+            hits,
+            dented,
+            misses: grid.wrong_guesses.clone(),
+            sunk,
+            decoys,
+            remaining,
+        }
+    }
+
+    /// Whether `coord` is part of a sunk ship or a revealed decoy.
+    pub fn is_sunk_at(&self, coord: Location) -> bool {
+        self.sunk.iter().any(|(_, coords)| coords.contains(&coord))
+            || self.decoys.iter().any(|coords| coords.contains(&coord))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_of_grid() {
+        let mut grid: Grid = "\
+            AA...
+            .....
+            B....
+            B....
+            B....
+        "
+        .parse()
+        .unwrap();
+        grid.guess_grid(Location::new(0, 0));
+        grid.guess_grid(Location::new(1, 0));
+        grid.guess_grid(Location::new(0, 3));
+        grid.guess_grid(Location::new(4, 4));
+
+        let fleet = [ShipClass::Destroyer, ShipClass::Cruiser];
+        let view = PlayerView::of_grid(&grid, &fleet, Disclosure::Class, false);
+        assert_eq!(view.hits, vec![Location::new(0, 3)]);
+        assert_eq!(view.misses, vec![Location::new(4, 4)]);
+        assert_eq!(
+            view.sunk,
+            vec![(
+                Some(ShipClass::Destroyer),
+                vec![Location::new(0, 0), Location::new(1, 0)]
+            )]
+        );
+        assert_eq!(view.remaining, vec![ShipClass::Cruiser]);
+        assert!(view.is_sunk_at(Location::new(1, 0)));
+        assert!(!view.is_sunk_at(Location::new(0, 3)));
+
+        let view = PlayerView::of_grid(&grid, &fleet, Disclosure::Sunk, false);
+        assert_eq!(
+            view.sunk,
+            vec![(None, vec![Location::new(0, 0), Location::new(1, 0)])]
+        );
+        assert_eq!(view.remaining, vec![ShipClass::Cruiser]);
+
+        // nothing has been sunk as far as the player knows
+        let view = PlayerView::of_grid(&grid, &fleet, Disclosure::HitOrMiss, false);
+        assert!(view.sunk.is_empty());
+        assert_eq!(
+            view.hits,
+            vec![
+                Location::new(0, 0),
+                Location::new(1, 0),
+                Location::new(0, 3)
+            ]
+        );
+        assert_eq!(view.remaining, fleet.to_vec());
+    }
+}
@@ -1,6 +1,5 @@
 use std::{
-    fmt::format,
-    io::{BufRead, BufReader, Read, Write},
+    io::{BufRead, BufReader, Write},
     process::Stdio,
 };
 
@@ -13,23 +12,24 @@ fn test_cmd_game() {
     let mut stdin = handle.stdin.take().unwrap();
     let mut stdout = BufReader::new(handle.stdout.take().unwrap());
 
-    stdin.write_all(b"1\n1\nH\n").unwrap();
-    stdin.write_all(b"1\n2\nH\n").unwrap();
-    stdin.write_all(b"1\n3\nH\n").unwrap();
-    stdin.write_all(b"1\n4\nH\n").unwrap();
-    stdin.write_all(b"1\n5\nH\n").unwrap();
+    stdin.write_all(b"A1\nH\n").unwrap();
+    stdin.write_all(b"B1\nH\n").unwrap();
+    stdin.write_all(b"C1\nH\n").unwrap();
+    stdin.write_all(b"D1\nH\n").unwrap();
+    stdin.write_all(b"E1\nH\n").unwrap();
 
-    stdin.write_all(b"1\n1\nV\n").unwrap();
-    stdin.write_all(b"2\n1\nV\n").unwrap();
-    stdin.write_all(b"3\n1\nV\n").unwrap();
-    stdin.write_all(b"4\n1\nV\n").unwrap();
-    stdin.write_all(b"5\n1\nV\n").unwrap();
+    stdin.write_all(b"A1\nV\n").unwrap();
+    stdin.write_all(b"A2\nV\n").unwrap();
+    stdin.write_all(b"A3\nV\n").unwrap();
+    stdin.write_all(b"A4\nV\n").unwrap();
+    stdin.write_all(b"A5\nV\n").unwrap();
 
     for i in 1..=5 {
         for j in 1..=[2, 3, 3, 4, 5][i - 1] {
-            let s1 = format!("{}\n{}\n", i, j);
+            let row = char::from(b'A' + j as u8 - 1);
+            let s1 = format!("{}{}\n", row, i);
             stdin.write_all(s1.as_bytes()).unwrap();
-            stdin.write_all(b"0\n0\n").unwrap();
+            stdin.write_all(b"(-1,-1)\n").unwrap();
         }
     }
 
@@ -43,4 +43,7 @@ fn test_cmd_game() {
             panic!("Failed because stdout never contained the Congratulations string");
         }
     }
+
+    drop(stdin);
+    handle.wait().unwrap();
 }
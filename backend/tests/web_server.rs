@@ -0,0 +1,440 @@
+use std::{net::SocketAddr, time::Duration};
+
+use futures::{SinkExt, StreamExt};
+use rust_learning::ServerConfig;
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn serve() -> SocketAddr {
+    serve_with(ServerConfig::default()).await
+}
+
+async fn serve_with(config: ServerConfig) -> SocketAddr {
+    let server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+        .serve(rust_learning::app_with_config(config).into_make_service());
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    addr
+}
+
+async fn connect(addr: SocketAddr, path: &str) -> Client {
+    connect_async(format!("ws://{addr}{path}")).await.unwrap().0
+}
+
+/// Makes a plain HTTP request and returns the status code and the body.
+async fn request(addr: SocketAddr, method: &str, path: &str, body: Option<Value>) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let body = body.map(|b| b.to_string());
+    let mut req = format!("{method} {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n");
+    match &body {
+        Some(body) => {
+            req += &format!(
+                "Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            )
+        }
+        None => req += "Content-Length: 0\r\n\r\n",
+    }
+    stream.write_all(req.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let status = response[9..12].parse().unwrap();
+    let (_, body) = response.split_once("\r\n\r\n").unwrap();
+    (status, body.to_string())
+}
+
+/// The next state the server sends, skipping anything that isn't JSON.
+async fn next_state(client: &mut Client) -> Value {
+    loop {
+        match client.next().await.unwrap().unwrap() {
+            Message::Text(text) => return serde_json::from_str(&text).unwrap(),
+            Message::Close(frame) => panic!("closed: {frame:?}"),
+            _ => {}
+        }
+    }
+}
+
+/// Two players on the public queue who have been paired, and their session tokens.
+async fn pair_with_tokens(addr: SocketAddr) -> ([Client; 2], [String; 2]) {
+    let mut first = connect(addr, "/ws").await;
+    let waiting = next_state(&mut first).await;
+    assert_eq!(waiting["type"], "Waiting");
+    let first_token = waiting["token"].as_str().unwrap().to_string();
+    let mut second = connect(addr, "/ws").await;
+    let second_token = next_state(&mut second).await["token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    for client in [&mut first, &mut second] {
+        assert_eq!(next_state(client).await["type"], "Adding");
+    }
+    ([first, second], [first_token, second_token])
+}
+
+async fn pair(addr: SocketAddr) -> [Client; 2] {
+    pair_with_tokens(addr).await.0
+}
+
+#[tokio::test]
+async fn test_concurrent_games() {
+    let addr = serve().await;
+    let mut games = vec![];
+    // none of these games finish, so each pair can only start if the ones before aren't in the way
+    for _ in 0..5 {
+        games.push(pair(addr).await);
+    }
+
+    // the games don't share anything: placing ships in one isn't seen in another
+    let place = r#"{"type":"AddShip","loc":{"x":0,"y":0},"dir":"Right"}"#;
+    games[2][0].send(Message::Text(place.into())).await.unwrap();
+    let state = next_state(&mut games[2][0]).await;
+    assert_eq!(state["ships"].as_array().unwrap().len(), 1);
+    let state = next_state(&mut games[2][1]).await;
+    assert_eq!(state["ships"].as_array().unwrap().len(), 0);
+}
+
+#[tokio::test]
+async fn test_player_leaving_the_queue() {
+    let addr = serve().await;
+    let mut gone = connect(addr, "/ws").await;
+    assert_eq!(next_state(&mut gone).await["type"], "Waiting");
+    gone.close(None).await.unwrap();
+    drop(gone);
+
+    // the next two players get each other rather than the one who left
+    pair(addr).await;
+}
+
+#[tokio::test]
+async fn test_private_room() {
+    let addr = serve().await;
+    let mut rules = json!({
+        "players": [
+            {"size": 6, "fleet": ["Destroyer"], "shots_per_turn": 1},
+            {"size": 6, "fleet": ["Destroyer"], "shots_per_turn": 1},
+        ],
+    });
+    let (status, body) = request(addr, "POST", "/rooms", Some(rules.clone())).await;
+    assert_eq!(status, 200);
+    let room: Value = serde_json::from_str(&body).unwrap();
+    let code = room["code"].as_str().unwrap().to_lowercase();
+    assert_eq!(room["players"], 0);
+    // seeds are strings so that JavaScript doesn't round them
+    let seed: u64 = room["seed"].as_str().unwrap().parse().unwrap();
+    assert_ne!(seed, 0);
+
+    // someone on the public queue isn't paired with anyone in the room
+    let mut stranger = connect(addr, "/ws").await;
+    assert_eq!(next_state(&mut stranger).await["type"], "Waiting");
+
+    let mut first = connect(addr, &format!("/ws/{code}")).await;
+    assert_eq!(next_state(&mut first).await["seed"], room["seed"]);
+    let (_, body) = request(addr, "GET", &format!("/rooms/{code}"), None).await;
+    assert_eq!(serde_json::from_str::<Value>(&body).unwrap()["players"], 1);
+
+    let mut second = connect(addr, &format!("/ws/{code}")).await;
+    assert_eq!(next_state(&mut second).await["type"], "Waiting");
+    for client in [&mut first, &mut second] {
+        let state = next_state(client).await;
+        assert_eq!(state["type"], "Adding");
+        assert_eq!(state["size"], 6);
+    }
+
+    let mut third = connect(addr, &format!("/ws/{code}")).await;
+    match third.next().await.unwrap().unwrap() {
+        Message::Close(Some(frame)) => assert_eq!(frame.reason, "Room Full"),
+        m => panic!("expected the room to be full, got {m:?}"),
+    }
+
+    rules["players"][0]["size"] = json!(100);
+    let (status, _) = request(addr, "POST", "/rooms", Some(rules.clone())).await;
+    assert_eq!(status, 400);
+
+    // rules that would leave the game stuck, or over before it starts, are turned down too
+    rules["players"][0]["size"] = json!(6);
+    for (field, value) in [
+        ("fleet", json!([])),
+        ("fleet", json!(vec!["Carrier"; 8])),
+        ("shots_per_turn", json!(0)),
+    ] {
+        let mut bad = rules.clone();
+        bad["players"][1][field] = value;
+        let (status, body) = request(addr, "POST", "/rooms", Some(bad)).await;
+        assert_eq!(status, 400);
+        assert!(body.contains("player 2"), "{body}");
+    }
+    rules["armour"] = json!({"Destroyer": 0});
+    let (status, _) = request(addr, "POST", "/rooms", Some(rules)).await;
+    assert_eq!(status, 400);
+}
+
+#[tokio::test]
+async fn test_unknown_room() {
+    let addr = serve().await;
+    let (status, _) = request(addr, "GET", "/rooms/NOPE", None).await;
+    assert_eq!(status, 404);
+    assert!(connect_async(format!("ws://{addr}/ws/NOPE")).await.is_err());
+
+    // without a body the room gets the standard rules
+    let (_, body) = request(addr, "POST", "/rooms", None).await;
+    let room: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(room["rules"]["players"][0]["size"], 10);
+}
+
+#[tokio::test]
+async fn test_reconnect() {
+    let addr = serve().await;
+    let ([mut first, mut second], [token, _]) = pair_with_tokens(addr).await;
+
+    let place = r#"{"type":"AddShip","loc":{"x":0,"y":0},"dir":"Right"}"#;
+    first.send(Message::Text(place.into())).await.unwrap();
+    next_state(&mut first).await;
+    next_state(&mut second).await;
+    first.close(None).await.unwrap();
+    drop(first);
+
+    // coming back gets the game as it was left
+    let mut first = connect(addr, &format!("/ws/resume/{token}")).await;
+    let state = next_state(&mut first).await;
+    assert_eq!(state["type"], "Adding");
+    assert_eq!(state["ships"].as_array().unwrap().len(), 1);
+
+    // and the game carries on with the new connection
+    let place = r#"{"type":"AddShip","loc":{"x":0,"y":4},"dir":"Up"}"#;
+    first.send(Message::Text(place.into())).await.unwrap();
+    let state = next_state(&mut first).await;
+    // up is up the screen, towards row A
+    assert_eq!(
+        state["ships"][1],
+        json!([{"x": 0, "y": 4}, {"x": 0, "y": 3}, {"x": 0, "y": 2}])
+    );
+    assert_eq!(next_state(&mut second).await["type"], "Adding");
+
+    // a token the server doesn't know is turned away in a way browsers can see
+    let mut unknown = connect(addr, "/ws/resume/nope").await;
+    match unknown.next().await.unwrap().unwrap() {
+        Message::Close(Some(frame)) => assert_eq!(frame.reason, "Unknown Session"),
+        m => panic!("expected the session to be turned away, got {m:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_forfeit() {
+    let addr = serve_with(ServerConfig {
+        grace_period: Duration::from_millis(100),
+    })
+    .await;
+    let ([mut first, second], [_, token]) = pair_with_tokens(addr).await;
+    drop(second);
+
+    let won = next_state(&mut first).await;
+    assert_eq!(won["type"], "Won");
+    assert_eq!(won["who"], "Player1");
+    assert_eq!(won["forfeit"], true);
+    match first.next().await.unwrap().unwrap() {
+        Message::Close(Some(frame)) => assert_eq!(frame.reason, "Game Finished"),
+        m => panic!("expected the game to be over, got {m:?}"),
+    }
+
+    // coming back after the game is over shows how it ended
+    let mut second = connect(addr, &format!("/ws/resume/{token}")).await;
+    let lost = next_state(&mut second).await;
+    assert_eq!(lost["type"], "Won");
+    assert_eq!(lost["who"], "Player1");
+    assert_eq!(lost["forfeit"], true);
+    match second.next().await.unwrap().unwrap() {
+        Message::Close(Some(frame)) => assert_eq!(frame.reason, "Game Finished"),
+        m => panic!("expected the game to be over, got {m:?}"),
+    }
+}
+
+/// Makes a room for a quick game on a 3x3 board with one destroyer each.
+async fn small_room(addr: SocketAddr) -> String {
+    let player = json!({"size": 3, "fleet": ["Destroyer"], "shots_per_turn": 1});
+    let rules = json!({ "players": [player, player] });
+    let (_, body) = request(addr, "POST", "/rooms", Some(rules)).await;
+    let room: Value = serde_json::from_str(&body).unwrap();
+    room["code"].as_str().unwrap().to_string()
+}
+
+async fn join_room(addr: SocketAddr, code: &str) -> [Client; 2] {
+    let mut first = connect(addr, &format!("/ws/{code}")).await;
+    assert_eq!(next_state(&mut first).await["type"], "Waiting");
+    let mut second = connect(addr, &format!("/ws/{code}")).await;
+    assert_eq!(next_state(&mut second).await["type"], "Waiting");
+    for client in [&mut first, &mut second] {
+        assert_eq!(next_state(client).await["type"], "Adding");
+    }
+    [first, second]
+}
+
+/// Sends a command and waits for both players to hear about it.
+async fn play(players: &mut [Client; 2], player: usize, command: Value) {
+    let command = Message::Text(command.to_string());
+    players[player].send(command).await.unwrap();
+    for client in players.iter_mut() {
+        next_state(client).await;
+    }
+}
+
+#[tokio::test]
+async fn test_armour() {
+    let addr = serve().await;
+    let player = json!({"size": 3, "fleet": ["Destroyer"], "shots_per_turn": 1});
+    let rules = json!({"players": [player, player], "armour": {"Destroyer": 2}});
+    let (_, body) = request(addr, "POST", "/rooms", Some(rules)).await;
+    let code = serde_json::from_str::<Value>(&body).unwrap()["code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let mut players = join_room(addr, &code).await;
+
+    let place = |y| json!({"type": "AddShip", "loc": {"x": 0, "y": y}, "dir": "Right"});
+    play(&mut players, 0, place(0)).await;
+    let command = Message::Text(place(2).to_string());
+    players[1].send(command).await.unwrap();
+    for client in players.iter_mut() {
+        next_state(client).await;
+        assert_eq!(next_state(client).await["type"], "Guessing");
+    }
+
+    // the armour holds, and both players can see where it was hit
+    let guess = json!({"type": "GuessPos", "loc": {"x": 0, "y": 2}});
+    players[0]
+        .send(Message::Text(guess.to_string()))
+        .await
+        .unwrap();
+    let shooter = next_state(&mut players[0]).await;
+    assert_eq!(shooter["your_dented"], json!([{"x": 0, "y": 2}]));
+    assert_eq!(shooter["your_correct_guesses"], json!([]));
+    let target = next_state(&mut players[1]).await;
+    assert_eq!(target["opps_dented"], json!([{"x": 0, "y": 2}]));
+}
+
+#[tokio::test]
+async fn test_spectator() {
+    let addr = serve().await;
+    let code = small_room(addr).await;
+    // the spectator is there before the players
+    let mut spectator = connect(addr, &format!("/ws/watch/{code}")).await;
+    let mut players = join_room(addr, &code).await;
+
+    let state = next_state(&mut spectator).await;
+    assert_eq!(state["type"], "Spectating");
+    assert_eq!(state["ships_placed"], json!([0, 0]));
+
+    let place = |y| json!({"type": "AddShip", "loc": {"x": 0, "y": y}, "dir": "Right"});
+    play(&mut players, 0, place(0)).await;
+    let state = next_state(&mut spectator).await;
+    assert_eq!(state["ships_placed"], json!([1, 0]));
+    // where the ship is stays secret
+    assert!(!state.to_string().contains("ships\""));
+
+    // spectators can't play
+    let guess = Message::Text(
+        json!({"type": "AddShip", "loc": {"x": 0, "y": 0}, "dir": "Right"}).to_string(),
+    );
+    spectator.send(guess).await.unwrap();
+
+    // placing the last ship shows both the last placement and the start of play
+    let command = Message::Text(place(2).to_string());
+    players[1].send(command).await.unwrap();
+    for client in players.iter_mut() {
+        assert_eq!(
+            next_state(client).await["ships"].as_array().unwrap().len(),
+            1
+        );
+        assert_eq!(next_state(client).await["type"], "Guessing");
+    }
+
+    let guess = |x, y| json!({"type": "GuessPos", "loc": {"x": x, "y": y}});
+    play(&mut players, 0, guess(0, 2)).await;
+    play(&mut players, 1, guess(2, 2)).await;
+    play(&mut players, 0, guess(1, 2)).await;
+
+    // the spectator catches up to the end, where everything is shown
+    let finished = loop {
+        let state = next_state(&mut spectator).await;
+        if state["type"] == "Finished" {
+            break state;
+        }
+        assert_eq!(state["type"], "Spectating");
+    };
+    assert_eq!(finished["winner"], "Player1");
+    assert_eq!(
+        finished["ships"][0],
+        json!([[{"x": 0, "y": 0}, {"x": 1, "y": 0}]])
+    );
+    assert_eq!(finished["stats"]["players"][0]["shots"], 2);
+    match spectator.next().await.unwrap().unwrap() {
+        Message::Close(Some(frame)) => assert_eq!(frame.reason, "Game Finished"),
+        m => panic!("expected the game to be over, got {m:?}"),
+    }
+
+    assert!(connect_async(format!("ws://{addr}/ws/watch/NOPE"))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_delayed_spectator() {
+    let addr = serve().await;
+    let code = small_room(addr).await;
+    let mut players = join_room(addr, &code).await;
+    let mut spectator = connect(addr, &format!("/ws/watch/{code}?delay=1")).await;
+
+    let placed = std::time::Instant::now();
+    let place = json!({"type": "AddShip", "loc": {"x": 0, "y": 0}, "dir": "Right"});
+    play(&mut players, 0, place).await;
+
+    // nothing has happened long enough ago to show yet
+    let early = tokio::time::timeout(Duration::from_millis(500), spectator.next()).await;
+    assert!(early.is_err());
+
+    loop {
+        let state = next_state(&mut spectator).await;
+        if state["ships_placed"] == json!([1, 0]) {
+            break;
+        }
+    }
+    assert!(placed.elapsed() >= Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn test_spectator_after_the_game() {
+    let addr = serve().await;
+    let code = small_room(addr).await;
+    let mut players = join_room(addr, &code).await;
+
+    let place = |y| json!({"type": "AddShip", "loc": {"x": 0, "y": y}, "dir": "Right"});
+    play(&mut players, 0, place(0)).await;
+    let command = Message::Text(place(2).to_string());
+    players[1].send(command).await.unwrap();
+    for client in players.iter_mut() {
+        next_state(client).await;
+        assert_eq!(next_state(client).await["type"], "Guessing");
+    }
+    let guess = |x, y| json!({"type": "GuessPos", "loc": {"x": x, "y": y}});
+    play(&mut players, 0, guess(0, 2)).await;
+    play(&mut players, 1, guess(2, 2)).await;
+    play(&mut players, 0, guess(1, 2)).await;
+    for client in players.iter_mut() {
+        assert_eq!(next_state(client).await["type"], "Won");
+    }
+
+    // turning up late still shows how it ended
+    let mut spectator = connect(addr, &format!("/ws/watch/{code}")).await;
+    let finished = next_state(&mut spectator).await;
+    assert_eq!(finished["type"], "Finished");
+    assert_eq!(finished["winner"], "Player1");
+    match spectator.next().await.unwrap().unwrap() {
+        Message::Close(Some(frame)) => assert_eq!(frame.reason, "Game Finished"),
+        m => panic!("expected the game to be over, got {m:?}"),
+    }
+}
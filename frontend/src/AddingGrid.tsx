@@ -7,17 +7,22 @@ function AddingGrid(props: { state: AddingState }) {
 
   const state = props.state;
 
-  const hasShips: boolean[][] = [];
-  for (let i = 0; i < state.size; i++) {
-    const temp = [];
-    for (let j = 0; j < state.size; j++) {
-      temp.push(false);
+  // one grid for the surface and one for each layer under it
+  const layers = [...Array(state.depths + 1).keys()];
+  const hasShips: boolean[][][] = layers.map(() => {
+    const layer = [];
+    for (let i = 0; i < state.size; i++) {
+      const temp = [];
+      for (let j = 0; j < state.size; j++) {
+        temp.push(false);
+      }
+      layer.push(temp);
     }
-    hasShips.push(temp);
-  }
+    return layer;
+  });
   for (const s of state.ships) {
     for (const l of s) {
-      hasShips[l.y][l.x] = true;
+      hasShips[l.layer ?? 0][l.y][l.x] = true;
     }
   }
 
@@ -27,23 +32,33 @@ function AddingGrid(props: { state: AddingState }) {
 
   return (
     <div id="adding-grid">
-      {[...Array(state.size).keys()].map((y) => (
-        <div key={y}>
-          {[...Array(state.size).keys()].map((x) => (
-            <button
-              className="grid-field"
-              key={x}
-              onClick={() => {
-                const comm: Command = { type: "AddShip", loc: { x, y }, dir };
-                ws.send(JSON.stringify(comm));
-                console.log(`You clicked ${x}, ${y}`);
-              }}
-            >
-              {hasShips[y][x] ? `🚢` : `🌊`}
-            </button>
-          ))}
-        </div>
-      ))}
+      <div id="adding-layers">
+        {layers.map((layer) => (
+          <div key={layer}>
+            {state.depths > 0 && (
+              <div>{layer === 0 ? "Surface" : `Depth ${layer}`}</div>
+            )}
+            {[...Array(state.size).keys()].map((y) => (
+              <div key={y}>
+                {[...Array(state.size).keys()].map((x) => (
+                  <button
+                    className="grid-field"
+                    key={x}
+                    onClick={() => {
+                      const loc = layer === 0 ? { x, y } : { x, y, layer };
+                      const comm: Command = { type: "AddShip", loc, dir };
+                      ws.send(JSON.stringify(comm));
+                      console.log(`You clicked ${x}, ${y}, ${layer}`);
+                    }}
+                  >
+                    {hasShips[layer][y][x] ? `🚢` : `🌊`}
+                  </button>
+                ))}
+              </div>
+            ))}
+          </div>
+        ))}
+      </div>
       <br />
       <div>
         <input
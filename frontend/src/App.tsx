@@ -7,8 +7,17 @@ function App() {
 
   return (
     <div id="main">
+      {state.type === "Connecting" && <p>Connecting...</p>}
       {state.type === "Waiting" && <p>Waiting for second player...</p>}
       {state.type === "Adding" && <AddingGrid state={state} />}
+      {state.type === "Won" && (
+        <p>
+          {state.who} won{state.forfeit && " because the other player left"}
+        </p>
+      )}
+      {state.type === "Disconnected" && (
+        <p>Lost the connection to the game.</p>
+      )}
       <p id="state">{JSON.stringify(state)}</p>
     </div>
   );
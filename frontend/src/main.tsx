@@ -7,6 +7,8 @@ import { create } from "zustand";
 export type Location = {
   x: number;
   y: number;
+  // left out on the surface
+  layer?: number;
 };
 
 export type Player = "Player1" | "Player2";
@@ -15,6 +17,14 @@ export type AddingState = {
   type: "Adding";
   ships: [[Location]];
   size: number;
+  depths: number;
+  seed: string;
+  // why the last PlaceFleet was turned down
+  errors?: [ShipRejection];
+};
+export type ShipRejection = {
+  ship: number | null;
+  reason: string;
 };
 export type GuessingState = {
   type: "Guessing";
@@ -23,50 +33,163 @@ export type GuessingState = {
 
   your_correct_guesses: [Location];
   your_incorrect_guesses: [Location];
+  // armoured cells that have been hit but can take more hits
+  your_dented: [Location];
 
   opps_correct_guesses: [Location];
   opps_incorrect_guesses: [Location];
+  opps_dented: [Location];
   your_ships: [[Location]];
 
   size: number;
+  opps_size: number;
+  depths: number;
+  repairs_left: number;
+};
+export type ShipStats = {
+  class: string;
+  first_hit_to_sink: number | null;
+  turns_survived: number;
+};
+export type PlayerStats = {
+  shots: number;
+  hits: number;
+  hit_rate: number;
+  longest_streak: number;
+  turns: number;
+  fleet: ShipStats[];
 };
 export type WonState = {
   type: "Won";
   who: Player;
+  stats: { players: [PlayerStats, PlayerStats] };
+  forfeit: boolean;
+};
+
+// one player's board as their opponent sees it
+export type PlayerView = {
+  size: number;
+  topology: "Flat" | "Torus" | "Hex";
+  depths: number;
+  hits: Location[];
+  dented: Location[];
+  misses: Location[];
+  // the class is null when the rules don't say what was sunk
+  sunk: [string | null, Location[]][];
+  decoys: Location[][];
+  remaining: string[];
+};
+// what spectators are sent while the game is going on
+export type SpectatingState = {
+  type: "Spectating";
+  turn: Player | null;
+  ships_placed: [number, number];
+  boards: [PlayerView, PlayerView];
+};
+// and once it's over, when everything can be shown
+export type FinishedState = {
+  type: "Finished";
+  winner: Player;
+  forfeit: boolean;
+  boards: [PlayerView, PlayerView];
+  ships: [Location[][], Location[][]];
+  stats: { players: [PlayerStats, PlayerStats] };
+};
+
+export type PracticingState = {
+  type: "Practicing";
+  correct_guesses: [Location];
+  incorrect_guesses: [Location];
+  shots: number;
+  size: number;
+  seed: string;
+};
+export type ClearedState = {
+  type: "Cleared";
+  shots: number;
+  seed: string;
 };
 
 export type GameState =
-  | { type: "Waiting" }
+  | { type: "Connecting" }
+  | { type: "Disconnected" }
+  | { type: "Waiting"; seed: string; token: string }
   | AddingState
   | GuessingState
-  | WonState;
+  | WonState
+  | SpectatingState
+  | FinishedState
+  | PracticingState
+  | ClearedState;
 
 export type AddShipCommand = {
   type: "AddShip";
   loc: Location;
   dir: ShipDir;
 };
-export type ShipDir = "Horz" | "Vert";
+// as they look on the screen, so "Up" goes towards row A. "Horz" and "Vert" are right and down
+export type ShipDir = "Horz" | "Vert" | "Up" | "Down" | "Left" | "Right";
+export type PlaceFleetCommand = {
+  type: "PlaceFleet";
+  ships: [{ loc: Location; dir: ShipDir }];
+};
 export type GuessPosCommand = {
   type: "GuessPos";
   loc: Location;
 };
 
-export type Command = AddShipCommand | GuessPosCommand;
+export type RepairCommand = {
+  type: "Repair";
+  loc: Location;
+};
+
+export type Command =
+  | AddShipCommand
+  | PlaceFleetCommand
+  | GuessPosCommand
+  | RepairCommand;
 
 export const useGameState = create<GameState>(() => ({
-  type: "Waiting",
+  type: "Connecting",
 }));
-export const ws = new WebSocket("ws://127.0.0.1:3000/ws");
-ws.onmessage = (event) => {
-  if (typeof event.data === "string") {
-    const state = JSON.parse(event.data);
-    useGameState.setState(state as GameState, true);
-  }
-};
-ws.onclose = () => {
-  console.log("connection closed");
-};
+// lets us back into the game if the connection drops
+let token: string | undefined;
+// the server gives up on us after a minute, so stop trying around then too
+const MAX_RETRIES = 60;
+let retries = 0;
+const FINAL_STATES: GameState["type"][] = ["Won", "Finished", "Cleared"];
+
+function connect(url: string): WebSocket {
+  const socket = new WebSocket(url);
+  socket.onmessage = (event) => {
+    retries = 0;
+    if (typeof event.data === "string") {
+      const state = JSON.parse(event.data) as GameState;
+      if (state.type === "Waiting") {
+        token = state.token;
+      }
+      useGameState.setState(state, true);
+    }
+  };
+  socket.onclose = (event) => {
+    console.log("connection closed");
+    // the server says when the game is over or it has forgotten our token (1008), anything else
+    // is worth coming back from
+    const over = [1000, 1008, 1011].includes(event.code);
+    if (token && !over && retries < MAX_RETRIES) {
+      retries += 1;
+      setTimeout(() => {
+        ws = connect(`ws://127.0.0.1:3000/ws/resume/${token}`);
+      }, 1000);
+    } else if (!FINAL_STATES.includes(useGameState.getState().type)) {
+      // how a finished game ended stays on the screen, anything else can't carry on
+      useGameState.setState({ type: "Disconnected" }, true);
+    }
+  };
+  return socket;
+}
+
+export let ws = connect("ws://127.0.0.1:3000/ws");
 
 ReactDOM.createRoot(document.getElementById("root")!).render(
   <React.StrictMode>